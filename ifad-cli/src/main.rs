@@ -1,6 +1,9 @@
-use clap::{App, Arg, ArgMatches, Values, AppSettings};
-use std::io::BufReader;
-use ifad::{MetadataReader, Annotation, Gene, Index, Segment, GafExporter, Query};
+use clap::{App, Arg, ArgMatches, Values, AppSettings, SubCommand};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use ifad::{MetadataReader, Annotation, Gene, Index, Segment, GafExporter, GpadExporter, Query, Diagnostics, Compression, IndexOptions, NegationPolicy};
+use ifad::{AnnotationRecord, GeneRecord, GpadRecord, GpiRecord, EcoMapping, EcoOntology, EvidencePolicy, Records, Record};
+use ifad::{AliasIndex, Ontology, Predicate, SlimMapping, TermIndex, ValidationPolicy, normalize_terms, write_enrichment_tsv};
 use std::convert::TryFrom;
 
 fn app<'a, 'b>() -> clap::App<'a, 'b> {
@@ -12,8 +15,8 @@ fn app<'a, 'b>() -> clap::App<'a, 'b> {
             if !&["F", "C", "P"].contains(&aspect) {
                 return Err("aspect must be one of F, C, or P".to_string());
             }
-            if !&["EXP", "OTHER", "UNKNOWN", "UNANNOTATED"].contains(&status) {
-                return Err("status must be one of EXP, OTHER, UNKNOWN, or UNANNOTATED".to_string());
+            if !&["EXP", "OTHER", "UNKNOWN", "UNANNOTATED", "NEGATED"].contains(&status) {
+                return Err("status must be one of EXP, OTHER, UNKNOWN, UNANNOTATED, or NEGATED".to_string());
             }
             return Ok(());
         }
@@ -22,26 +25,27 @@ fn app<'a, 'b>() -> clap::App<'a, 'b> {
 
     App::new("ifad")
         .setting(AppSettings::DeriveDisplayOrder)
-        .arg(Arg::with_name("genes")
-            .help("the file to read genes from (e.g. gene-types.txt")
-            .long("--genes")
-            .require_equals(true)
-            .takes_value(true))
-        .arg(Arg::with_name("annotations")
-            .help("the file to read annotations from (e.g. tair.gaf)")
-            .long("--annotations")
-            .require_equals(true)
-            .takes_value(true))
+        .args(&input_args())
         .arg(Arg::with_name("genes_out")
-            .help("the file to write queried genes to (e.g. gene-types_F-EXP.txt")
+            .help("the file to write queried genes to, compressed if it ends in .gz, .bz2, or .zst (e.g. gene-types_F-EXP.txt")
             .long("--genes-out")
             .require_equals(true)
             .takes_value(true))
         .arg(Arg::with_name("annotations_out")
-            .help("the file to write queried annotations to (e.g. tair_F-EXP.gaf)")
+            .help("the file to write queried annotations to, compressed if it ends in .gz, .bz2, or .zst (e.g. tair_F-EXP.gaf)")
             .long("--annotations-out")
             .require_equals(true)
             .takes_value(true))
+        .arg(Arg::with_name("gpad_out")
+            .help("a file to also write queried annotations to as GPAD 2.0 (e.g. tair_F-EXP.gpad)")
+            .long("--gpad-out")
+            .require_equals(true)
+            .takes_value(true))
+        .arg(Arg::with_name("gpi_out")
+            .help("a file to also write the genes of queried annotations to as GPI 2.0 (e.g. tair_F-EXP.gpi)")
+            .long("--gpi-out")
+            .require_equals(true)
+            .takes_value(true))
         .arg(Arg::with_name("query")
             .help("the type of query")
             .long("--query")
@@ -55,6 +59,211 @@ fn app<'a, 'b>() -> clap::App<'a, 'b> {
             .require_equals(true)
             .takes_value(true)
             .validator(segment_validator))
+        .arg(Arg::with_name("term")
+            .help("a GO term whose genes to use in the query, including genes annotated to its descendants if the ontology is given (e.g. GO:0015979)")
+            .multiple(true)
+            .long("--term")
+            .require_equals(true)
+            .takes_value(true))
+        .arg(Arg::with_name("not_term")
+            .help("a GO term whose genes to leave out of the query, like --term (e.g. GO:0005739)")
+            .multiple(true)
+            .long("--not-term")
+            .require_equals(true)
+            .takes_value(true))
+        .arg(Arg::with_name("exact_terms")
+            .help("only match annotations made directly to the terms given by --term and --not-term, not to their descendants")
+            .long("--exact-terms"))
+        .arg(Arg::with_name("where")
+            .help("a query written as an expression, instead of --segment, --term, and --not-term (e.g. \"F:EXP and not C:UNANNOTATED\")")
+            .long("--where")
+            .require_equals(true)
+            .takes_value(true)
+            .conflicts_with_all(&["segment", "term", "not_term"]))
+        .arg(Arg::with_name("product_type")
+            .help("only query genes of these gene product types (e.g. protein_coding)")
+            .long("--product-type")
+            .require_equals(true)
+            .takes_value(true)
+            .use_delimiter(true))
+        .arg(Arg::with_name("exclude_product_type")
+            .help("leave genes of these gene product types out of the query (e.g. other_rna,pseudogene)")
+            .long("--exclude-product-type")
+            .require_equals(true)
+            .takes_value(true)
+            .use_delimiter(true))
+        .arg(Arg::with_name("gene_list")
+            .help("only query the genes named in this file, one ID or alias per line (e.g. drought-genes.txt)")
+            .long("--gene-list")
+            .require_equals(true)
+            .takes_value(true))
+        .arg(Arg::with_name("annotated_genes")
+            .help("only keep queried genes which still have queried annotations, e.g. after filtering them with --where")
+            .long("--annotated-genes"))
+        .arg(Arg::with_name("slim")
+            .help("a GO slim subset of the ontology to map annotations to (e.g. goslim_plant)")
+            .long("--slim")
+            .require_equals(true)
+            .takes_value(true)
+            .requires_all(&["ontology", "slim_out"]))
+        .arg(Arg::with_name("slim_out")
+            .help("the file to write the number of genes mapped to each slim term to, as TSV (e.g. tair_goslim_plant.tsv)")
+            .long("--slim-out")
+            .require_equals(true)
+            .takes_value(true)
+            .requires("slim"))
+        .arg(Arg::with_name("enrichment_out")
+            .help("the file to write the enrichment of GO terms among the queried genes to, as TSV (e.g. tair_F-EXP_enrichment.tsv)")
+            .long("--enrichment-out")
+            .require_equals(true)
+            .takes_value(true)
+            .requires("ontology"))
+        .subcommand(SubCommand::with_name("stats")
+            .about("Prints the number of genes and annotations in each segment")
+            .setting(AppSettings::DeriveDisplayOrder)
+            .args(&input_args())
+            .arg(Arg::with_name("format")
+                .help("how to print the statistics")
+                .long("--format")
+                .possible_values(&["table", "tsv", "json"])
+                .default_value("table")
+                .require_equals(true))
+            .arg(Arg::with_name("out")
+                .help("the file to write the statistics to, instead of printing them")
+                .long("--out")
+                .require_equals(true)
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("combinations")
+            .about("Prints the number of genes with each combination of statuses across the three aspects")
+            .setting(AppSettings::DeriveDisplayOrder)
+            .args(&input_args())
+            .arg(Arg::with_name("format")
+                .help("how to print the combinations; tsv and json include every combination")
+                .long("--format")
+                .possible_values(&["table", "tsv", "json"])
+                .default_value("table")
+                .require_equals(true))
+            .arg(Arg::with_name("top")
+                .help("the number of the most populated combinations to print in a table")
+                .long("--top")
+                .default_value("10")
+                .require_equals(true))
+            .arg(Arg::with_name("out")
+                .help("the file to write the combinations to, instead of printing them")
+                .long("--out")
+                .require_equals(true)
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("gene")
+            .about("Prints the status of a gene for each aspect, along with its annotations")
+            .setting(AppSettings::DeriveDisplayOrder)
+            .arg(Arg::with_name("name")
+                .help("the ID or any other name of the gene (e.g. AT1G74030 or ENO1)")
+                .required(true))
+            .args(&input_args()))
+}
+
+/// The arguments for reading genes and annotations and classifying them,
+/// which every command takes.
+fn input_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("genes")
+            .help("the file to read genes from (e.g. gene-types.txt")
+            .long("--genes")
+            .require_equals(true)
+            .takes_value(true),
+        Arg::with_name("annotations")
+            .help("the file to read annotations from (e.g. tair.gaf)")
+            .long("--annotations")
+            .require_equals(true)
+            .takes_value(true),
+        Arg::with_name("gpad")
+            .help("a GPAD 2.0 file to read annotations from instead of a GAF file")
+            .long("--gpad")
+            .require_equals(true)
+            .takes_value(true)
+            .requires("gpi")
+            .conflicts_with_all(&["genes", "annotations"]),
+        Arg::with_name("gpi")
+            .help("the GPI 2.0 file describing the genes of the GPAD annotations")
+            .long("--gpi")
+            .require_equals(true)
+            .takes_value(true)
+            .requires("gpad"),
+        Arg::with_name("negated")
+            .help("how NOT annotations classify genes: ignored, as a separate NEGATED status, or like other annotations")
+            .long("--negated")
+            .possible_values(&["ignore", "separate", "include"])
+            .default_value("ignore")
+            .require_equals(true),
+        Arg::with_name("evidence_policy")
+            .help("a TOML or JSON file giving the experimental, unknown, and excluded evidence codes")
+            .long("--evidence-policy")
+            .require_equals(true)
+            .takes_value(true),
+        Arg::with_name("experimental_codes")
+            .help("the evidence codes which classify annotations as experimental (e.g. EXP,IDA,IPI)")
+            .long("--experimental-codes")
+            .require_equals(true)
+            .takes_value(true)
+            .use_delimiter(true),
+        Arg::with_name("unknown_codes")
+            .help("the evidence codes which classify annotations as unknown (e.g. ND)")
+            .long("--unknown-codes")
+            .require_equals(true)
+            .takes_value(true)
+            .use_delimiter(true),
+        Arg::with_name("excluded_codes")
+            .help("the evidence codes of annotations to leave out of classification (e.g. IEA)")
+            .long("--excluded-codes")
+            .require_equals(true)
+            .takes_value(true)
+            .use_delimiter(true),
+        Arg::with_name("eco_mapping")
+            .help("a file mapping GO evidence codes to ECO classes (e.g. gaf-eco-mapping.txt)")
+            .long("--eco-mapping")
+            .require_equals(true)
+            .takes_value(true),
+        Arg::with_name("eco_ontology")
+            .help("the ECO ontology, for matching ECO classes by their ancestors (e.g. eco.obo)")
+            .long("--eco-ontology")
+            .require_equals(true)
+            .takes_value(true),
+        Arg::with_name("ontology")
+            .help("the Gene Ontology, for checking the aspects and terms of annotations (e.g. go-basic.obo or go-basic.json)")
+            .long("--ontology")
+            .require_equals(true)
+            .takes_value(true),
+        Arg::with_name("validate")
+            .help("what to do with annotations whose aspect does not match their term, or whose term is obsolete [default: report]")
+            .long("--validate")
+            .possible_values(&["report", "correct", "drop"])
+            .takes_value(true)
+            .requires("ontology")
+            .require_equals(true),
+        Arg::with_name("normalize")
+            .help("rewrite annotations to alternative or replaced GO IDs to use the primary ID of the current term")
+            .long("--normalize")
+            .requires("ontology"),
+        Arg::with_name("normalized_out")
+            .help("a file to write all of the normalized and validated annotations to as GAF (e.g. tair_normalized.gaf)")
+            .long("--normalized-out")
+            .require_equals(true)
+            .takes_value(true)
+            .requires("normalize"),
+        Arg::with_name("lenient")
+            .help("skip malformed rows instead of failing, reporting what was skipped")
+            .long("--lenient"),
+        Arg::with_name("aliases")
+            .help("a file of gene aliases, as tab-separated rows of a gene ID and its aliases (e.g. gene_aliases.txt)")
+            .long("--aliases")
+            .require_equals(true)
+            .takes_value(true),
+        Arg::with_name("diagnostics_out")
+            .help("the file to write a report of skipped rows and annotations to")
+            .long("--diagnostics-out")
+            .require_equals(true)
+            .takes_value(true),
+    ]
 }
 
 fn main() {
@@ -66,28 +275,154 @@ fn main() {
     }
 }
 
+/// Where to read genes and annotations from.
+enum Input<'a> {
+    Gaf { genes_path: &'a str, annos_path: &'a str },
+    Gpad { gpad_path: &'a str, gpi_path: &'a str },
+}
+
+/// How to read genes and annotations and classify them, for any command.
+struct InputConfig<'a> {
+    input: Input<'a>,
+    negated: &'a str,
+    evidence_policy: Option<&'a str>,
+    experimental_codes: Option<Values<'a>>,
+    unknown_codes: Option<Values<'a>>,
+    excluded_codes: Option<Values<'a>>,
+    eco_mapping: Option<&'a str>,
+    eco_ontology: Option<&'a str>,
+    ontology: Option<&'a str>,
+    validate: &'a str,
+    normalize: bool,
+    normalized_out: Option<&'a str>,
+    lenient: bool,
+    aliases: Option<&'a str>,
+    diagnostics_out: Option<&'a str>,
+}
+
+impl InputConfig<'_> {
+    fn from_args<'a>(args: &'a ArgMatches) -> Option<InputConfig<'a>> {
+        let input = match (args.value_of("gpad"), args.value_of("gpi")) {
+            (Some(gpad_path), Some(gpi_path)) => Input::Gpad { gpad_path, gpi_path },
+            _ => Input::Gaf {
+                genes_path: args.value_of("genes")?,
+                annos_path: args.value_of("annotations")?,
+            },
+        };
+        let negated = args.value_of("negated")?;
+        let evidence_policy = args.value_of("evidence_policy");
+        let experimental_codes = args.values_of("experimental_codes");
+        let unknown_codes = args.values_of("unknown_codes");
+        let excluded_codes = args.values_of("excluded_codes");
+        let eco_mapping = args.value_of("eco_mapping");
+        let eco_ontology = args.value_of("eco_ontology");
+        let ontology = args.value_of("ontology");
+        // A default value would always require the ontology
+        let validate = args.value_of("validate").unwrap_or("report");
+        let normalize = args.is_present("normalize");
+        let normalized_out = args.value_of("normalized_out");
+        let lenient = args.is_present("lenient");
+        let aliases = args.value_of("aliases");
+        let diagnostics_out = args.value_of("diagnostics_out");
+        Some(InputConfig {
+            input,
+            negated,
+            evidence_policy,
+            experimental_codes,
+            unknown_codes,
+            excluded_codes,
+            eco_mapping,
+            eco_ontology,
+            ontology,
+            validate,
+            normalize,
+            normalized_out,
+            lenient,
+            aliases,
+            diagnostics_out,
+        })
+    }
+}
+
+/// The options of the default command, which queries genes and annotations.
 struct Config<'a> {
-    genes_path: &'a str,
-    annos_path: &'a str,
+    input: InputConfig<'a>,
     genes_out: &'a str,
     annos_out: &'a str,
+    gpad_out: Option<&'a str>,
+    gpi_out: Option<&'a str>,
     query: &'a str,
-    segments: Values<'a>,
+    segments: Option<Values<'a>>,
+    terms: Option<Values<'a>>,
+    not_terms: Option<Values<'a>>,
+    exact_terms: bool,
+    expression: Option<&'a str>,
+    product_types: Option<Values<'a>>,
+    excluded_product_types: Option<Values<'a>>,
+    gene_list: Option<&'a str>,
+    annotated_genes: bool,
+    slim: Option<&'a str>,
+    slim_out: Option<&'a str>,
+    enrichment_out: Option<&'a str>,
 }
 
 impl Config<'_> {
     fn from_args<'a>(args: &'a ArgMatches) -> Option<Config<'a>> {
-        let genes_path = args.value_of("genes")?;
-        let annos_path = args.value_of("annotations")?;
+        let input = InputConfig::from_args(args)?;
         let genes_out = args.value_of("genes_out")?;
         let annos_out = args.value_of("annotations_out")?;
+        let gpad_out = args.value_of("gpad_out");
+        let gpi_out = args.value_of("gpi_out");
         let query = args.value_of("query")?;
-        let segments = args.values_of("segment")?;
-        Some(Config { genes_path, annos_path, genes_out, annos_out, query, segments })
+        let segments = args.values_of("segment");
+        let terms = args.values_of("term");
+        let not_terms = args.values_of("not_term");
+        let expression = args.value_of("where");
+        if segments.is_none() && terms.is_none() && not_terms.is_none() && expression.is_none() {
+            return None;
+        }
+        let exact_terms = args.is_present("exact_terms");
+        let product_types = args.values_of("product_type");
+        let excluded_product_types = args.values_of("exclude_product_type");
+        let gene_list = args.value_of("gene_list");
+        let annotated_genes = args.is_present("annotated_genes");
+        let slim = args.value_of("slim");
+        let slim_out = args.value_of("slim_out");
+        let enrichment_out = args.value_of("enrichment_out");
+        Some(Config {
+            input,
+            genes_out,
+            annos_out,
+            gpad_out,
+            gpi_out,
+            query,
+            segments,
+            terms,
+            not_terms,
+            exact_terms,
+            expression,
+            product_types,
+            excluded_product_types,
+            gene_list,
+            annotated_genes,
+            slim,
+            slim_out,
+            enrichment_out,
+        })
     }
 }
 
 fn run(args: &ArgMatches) -> Result<(), String> {
+    if let Some(stats_args) = args.subcommand_matches("stats") {
+        return run_stats(stats_args);
+    }
+    if let Some(combinations_args) = args.subcommand_matches("combinations") {
+        return run_combinations(combinations_args);
+    }
+    if let Some(gene_args) = args.subcommand_matches("gene") {
+        return run_gene(gene_args);
+    }
+
     let maybe_config = Config::from_args(args);
     let config = match maybe_config {
         Some(options) => options,
@@ -97,63 +432,445 @@ fn run(args: &ArgMatches) -> Result<(), String> {
         },
     };
 
-    let segments: Vec<Segment> = config.segments.map(|segment| {
+    let mut query = match config.expression {
+        Some(expression) => Query::parse(expression)
+            .map_err(|e| format!("invalid query:\n{}", e.highlight(expression)))?,
+        None => query_from_flags(&config),
+    };
+    if let Some(types) = config.product_types.clone() {
+        query = Query::And(vec![query, Predicate::GeneProductType(to_strings(types)).into()]);
+    }
+    if let Some(types) = config.excluded_product_types.clone() {
+        let excluded = Query::from(Predicate::GeneProductType(to_strings(types)));
+        query = Query::And(vec![query, Query::Not(Box::new(excluded))]);
+    }
+    let gene_list = match config.gene_list {
+        Some(path) => Some(read_gene_list(path)?),
+        None => None,
+    };
+    if let Some(names) = gene_list.clone() {
+        query = Query::And(vec![query, Predicate::Genes(names).into()]);
+    }
+    if config.annotated_genes {
+        query = Query::Annotated(Box::new(query));
+    }
+
+    with_index(&config.input, |loaded| {
+        let Loaded { inputs, ontology, eco, index } = loaded;
+
+        // Names in the gene list may be ambiguous aliases, or not name any gene at all
+        for name in gene_list.iter().flatten() {
+            match index.aliases.resolve(name) {
+                [] => eprintln!("Warning: {} in the gene list does not name any gene", name),
+                [_] => (),
+                gene_ids => eprintln!("Warning: {} in the gene list is an alias of {}, which are all queried",
+                                      name, gene_ids.join(", ")),
+            }
+        }
+
+        if let (Some(ontology), Some(subset), Some(slim_out)) = (ontology, config.slim, config.slim_out) {
+            let slim = SlimMapping::new(index, ontology, subset);
+            if slim.slim_terms().next().is_none() {
+                return Err(format!("the ontology has no terms in the subset {}", subset));
+            }
+            let slim_file = std::fs::File::create(slim_out)
+                .map_err(|e| format!("failed to create slim output file: {:?}", e))?;
+            slim.write_tsv(slim_file)
+                .map_err(|e| format!("failed to write slim counts: {:?}", e))?;
+        }
+
+        eprintln!("Executing query: {:?}", query);
+        let result = match ontology {
+            Some(ontology) => query.execute_with(index, ontology),
+            None => query.execute(index).ok_or_else(|| {
+                "GO terms in the query include their descendants, which needs --ontology (or use `exact GO:...`)".to_string()
+            })?,
+        };
+
+        if let (Some(ontology), Some(enrichment_out)) = (ontology, config.enrichment_out) {
+            let terms = TermIndex::new(index, ontology);
+            let enrichment = terms.enrichment(&result);
+            let enrichment_file = std::fs::File::create(enrichment_out)
+                .map_err(|e| format!("failed to create enrichment output file: {:?}", e))?;
+            write_enrichment_tsv(enrichment_file, &enrichment)
+                .map_err(|e| format!("failed to write enrichment: {:?}", e))?;
+        }
+
+        let mut genes_out = std::fs::File::create(config.genes_out)
+            .map_err(|e| format!("failed to create genes output file: {:?}", e))?;
+        let mut genes_exporter = GafExporter::new(
+            inputs.gene_metadata.to_string(),
+            inputs.gene_header.to_string(),
+            result.genes_iter().map(|gene| gene.record));
+        genes_exporter.write_compressed(&mut genes_out, Compression::from_path(config.genes_out))
+            .expect("should write genes file");
+
+        let mut annotations_out = std::fs::File::create(config.annos_out)
+            .map_err(|e| format!("failed to create annotations output file: {:?}", e))?;
+        let mut annotations_exporter = GafExporter::new(
+            inputs.anno_metadata.to_string(),
+            inputs.anno_header.to_string(),
+            result.annotations_iter().map(|anno| anno.record));
+        annotations_exporter.write_compressed(&mut annotations_out, Compression::from_path(config.annos_out))
+            .map_err(|e| format!("failed to export data as GAF: {:?}", e))?;
+
+        let gpad_exporter = GpadExporter::new(result.annotations_iter(), eco.clone());
+        if let Some(gpad_out) = config.gpad_out {
+            let gpad_file = std::fs::File::create(gpad_out)
+                .map_err(|e| format!("failed to create GPAD output file: {:?}", e))?;
+            gpad_exporter.write_gpad(gpad_file, Compression::from_path(gpad_out))
+                .map_err(|e| format!("failed to export data as GPAD: {:?}", e))?;
+        }
+        if let Some(gpi_out) = config.gpi_out {
+            let gpi_file = std::fs::File::create(gpi_out)
+                .map_err(|e| format!("failed to create GPI output file: {:?}", e))?;
+            gpad_exporter.write_gpi(gpi_file, Compression::from_path(gpi_out))
+                .map_err(|e| format!("failed to export data as GPI: {:?}", e))?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Prints the number of genes and annotations in each segment.
+fn run_stats(args: &ArgMatches) -> Result<(), String> {
+    let config = InputConfig::from_args(args)
+        .ok_or("stats needs --genes and --annotations, or --gpad and --gpi")?;
+    let format = args.value_of("format").unwrap_or("table");
+
+    with_index(&config, |loaded| {
+        let summary = loaded.index.summary();
+        let mut writer: Box<dyn Write> = match args.value_of("out") {
+            Some(path) => Box::new(std::fs::File::create(path)
+                .map_err(|e| format!("failed to create stats output file: {:?}", e))?),
+            None => Box::new(std::io::stdout()),
+        };
+        match format {
+            "table" => summary.write_table(&mut writer),
+            "tsv" => summary.write_tsv(&mut writer),
+            "json" => summary.write_json(&mut writer),
+            _ => unreachable!(),
+        }.map_err(|e| format!("failed to write stats: {:?}", e))
+    })
+}
+
+/// Prints the number of genes with each combination of statuses.
+fn run_combinations(args: &ArgMatches) -> Result<(), String> {
+    let config = InputConfig::from_args(args)
+        .ok_or("combinations needs --genes and --annotations, or --gpad and --gpi")?;
+    let format = args.value_of("format").unwrap_or("table");
+    let top: usize = args.value_of("top").unwrap_or("10").parse()
+        .map_err(|_| "--top must be a number")?;
+
+    with_index(&config, |loaded| {
+        let matrix = loaded.index.status_combinations();
+        let mut writer: Box<dyn Write> = match args.value_of("out") {
+            Some(path) => Box::new(std::fs::File::create(path)
+                .map_err(|e| format!("failed to create combinations output file: {:?}", e))?),
+            None => Box::new(std::io::stdout()),
+        };
+        match format {
+            "table" => matrix.write_table(&mut writer, top),
+            "tsv" => matrix.write_tsv(&mut writer),
+            "json" => matrix.write_json(&mut writer),
+            _ => unreachable!(),
+        }.map_err(|e| format!("failed to write combinations: {:?}", e))
+    })
+}
+
+/// Prints the status of a gene for each aspect, along with its annotations.
+fn run_gene(args: &ArgMatches) -> Result<(), String> {
+    let config = InputConfig::from_args(args)
+        .ok_or("gene needs --genes and --annotations, or --gpad and --gpi")?;
+    let name = args.value_of("name").expect("should get gene name");
+
+    with_index(&config, |loaded| {
+        let profile = match loaded.index.gene_profile(name) {
+            Some(profile) => profile,
+            None => return match loaded.index.aliases.resolve(name) {
+                [] => Err(format!("no gene is named {}", name)),
+                gene_ids => Err(format!("{} is an alias of more than one gene: {}", name, gene_ids.join(", "))),
+            },
+        };
+        profile.write_text(std::io::stdout())
+            .map_err(|e| format!("failed to write gene: {:?}", e))
+    })
+}
+
+/// Builds a flat query from the --segment, --term, and --not-term flags.
+fn query_from_flags(config: &Config) -> Query {
+    let mut predicates: Vec<Predicate> = config.segments.clone().into_iter().flatten().map(|segment| {
         let split: Vec<&str> = segment.split(',').collect();
         let segment = (split[0], split[1]);
-        Segment::try_from(segment).expect("should convert segment arg to Segment")
+        Segment::try_from(segment).expect("should convert segment arg to Segment").into()
     }).collect();
+    // Descendants of the terms can only be found with the ontology
+    let include_descendants = !config.exact_terms && config.input.ontology.is_some();
+    predicates.extend(config.terms.clone().into_iter().flatten().map(|term| {
+        Predicate::Term { term: term.to_string(), include_descendants }
+    }));
+    predicates.extend(config.not_terms.clone().into_iter().flatten().map(|term| {
+        Predicate::NotTerm { term: term.to_string(), include_descendants }
+    }));
+
+    match config.query {
+        "union" => Query::Union(predicates),
+        "intersection" => Query::Intersection(predicates),
+        _ => unreachable!(),
+    }
+}
+
+fn to_strings(values: Values) -> Vec<String> {
+    values.map(str::to_string).collect()
+}
+
+/// The input files, along with the Index built from them.
+struct Loaded<'a> {
+    inputs: &'a Inputs,
+    ontology: Option<&'a Ontology>,
+    eco: &'a EcoMapping,
+    index: &'a Index<'a, 'a>,
+}
+
+/// Reads the input files, checks them against the ontology if given, and
+/// classifies their genes, reporting any problems before running a command
+/// on the Index.
+fn with_index<F>(config: &InputConfig, command: F) -> Result<(), String>
+    where F: FnOnce(Loaded) -> Result<(), String>
+{
+    let eco = read_eco(config.eco_mapping, config.eco_ontology)?;
+
+    let mut diagnostics = Diagnostics::new();
+    let mut inputs = match config.input {
+        Input::Gaf { genes_path, annos_path } => read_gaf(genes_path, annos_path, config.lenient, &mut diagnostics)?,
+        Input::Gpad { gpad_path, gpi_path } => read_gpad(gpad_path, gpi_path, &eco, config.lenient, &mut diagnostics)?,
+    };
+
+    let ontology = match config.ontology {
+        Some(path) => Some(Ontology::parse_from(decompress(path, "ontology")?)
+            .map_err(|e| format!("failed to parse ontology in {}: {}", path, e))?),
+        None => None,
+    };
 
-    let mut genes_file = std::fs::File::open(config.genes_path)
-        .map_err(|e| format!("failed to open genes file: {:?}", e))?;
-    let mut gene_reader = MetadataReader::new(BufReader::new(&mut genes_file));
-    let gene_records = ifad::GeneRecord::parse_from(&mut gene_reader)
-        .map_err(|e| format!("failed to parse gene records: {:?}",e ))?;
-    let gene_metadata = gene_reader.metadata().expect("should capture gene metadata");
-    let gene_headers = gene_reader.header().expect("should get gene headers");
-
-    let mut annos_file = std::fs::File::open(config.annos_path)
-        .map_err(|e| format!("failed to open annotations file: {:?}", e))?;
-    let mut anno_reader = MetadataReader::new(BufReader::new(&mut annos_file));
-    let anno_records = ifad::AnnotationRecord::parse_from(&mut anno_reader)
-        .map_err(|e| format!("failed to parse annotation records: {:?}", e))?;
-    let anno_metadata = anno_reader.metadata().expect("should capture annotation metadata");
-    let anno_headers = anno_reader.header().expect("should capture annotation header");
+    // Check annotations against the ontology before they are classified
+    if let Some(ontology) = &ontology {
+        let validation = match config.validate {
+            "report" => ValidationPolicy::Report,
+            "correct" => ValidationPolicy::Correct,
+            "drop" => ValidationPolicy::Drop,
+            _ => unreachable!(),
+        };
+        if config.normalize {
+            diagnostics.append(normalize_terms(&mut inputs.anno_records, ontology));
+        }
+        diagnostics.append(validation.apply(&mut inputs.anno_records, ontology));
+
+        if let Some(normalized_out) = config.normalized_out {
+            let mut normalized_file = std::fs::File::create(normalized_out)
+                .map_err(|e| format!("failed to create normalized output file: {:?}", e))?;
+            let mut normalized_exporter = GafExporter::new(
+                inputs.anno_metadata.to_string(),
+                inputs.anno_header.to_string(),
+                inputs.anno_records.iter());
+            normalized_exporter.write_compressed(&mut normalized_file, Compression::from_path(normalized_out))
+                .map_err(|e| format!("failed to export normalized annotations: {:?}", e))?;
+        }
+    }
+    let gene_records = &inputs.gene_records;
+    let anno_records = &inputs.anno_records;
 
     let genes: Vec<Gene> = gene_records.iter()
         .map(|record| Gene::from_record(record))
         .collect();
 
-    let experimental_evidence = &["EXP", "IDA", "IPI", "IMP", "IGI", "IEP", "HTP", "HDA", "HMP", "HGI", "HEP"];
+    // Codes given as flags override those from the policy file
+    let mut evidence = match config.evidence_policy {
+        Some(path) => EvidencePolicy::load(path)
+            .map_err(|e| format!("failed to load evidence policy {}: {}", path, e))?,
+        None => EvidencePolicy::default(),
+    };
+    if let Some(codes) = config.experimental_codes.clone() { evidence.set_experimental(to_strings(codes)); }
+    if let Some(codes) = config.unknown_codes.clone() { evidence.set_unknown(to_strings(codes)); }
+    if let Some(codes) = config.excluded_codes.clone() { evidence.set_excluded(to_strings(codes)); }
+    let evidence = evidence.with_eco(eco.clone());
+
     let annotations: Vec<Annotation> = anno_records.iter()
-        .map(|record| Annotation::from_record(record, experimental_evidence))
+        .map(|record| Annotation::from_record(record, &evidence))
         .collect();
 
-    let index: Index = Index::new(&genes, &annotations);
-    let query = match config.query {
-        "union" => Query::Union(segments),
-        "intersection" => Query::Intersection(segments),
+    let negation = match config.negated {
+        "ignore" => NegationPolicy::Ignore,
+        "separate" => NegationPolicy::Separate,
+        "include" => NegationPolicy::Include,
         _ => unreachable!(),
     };
+    let options = IndexOptions { negation, evidence };
+    let mut aliases = AliasIndex::new(&genes);
+    if let Some(path) = config.aliases {
+        aliases.read_aliases(decompress(path, "aliases")?)
+            .map_err(|e| format!("failed to read aliases in {}: {}", path, e))?;
+    }
+    let index: Index = Index::with_aliases(&genes, &annotations, options, aliases);
+    diagnostics.append(index.diagnostics());
+    if !diagnostics.is_empty() {
+        eprintln!("Warning: {}", diagnostics);
+    }
+    if let Some(diagnostics_out) = config.diagnostics_out {
+        let mut diagnostics_file = std::fs::File::create(diagnostics_out)
+            .map_err(|e| format!("failed to create diagnostics output file: {:?}", e))?;
+        for diagnostic in diagnostics.iter() {
+            writeln!(diagnostics_file, "{}", diagnostic)
+                .map_err(|e| format!("failed to write diagnostics: {:?}", e))?;
+        }
+    }
+
+    command(Loaded { inputs: &inputs, ontology: ontology.as_ref(), eco: &eco, index: &index })
+}
+
+/// The records read from the input files, along with the metadata and
+/// header to write at the top of the corresponding output files.
+struct Inputs {
+    gene_records: Vec<GeneRecord>,
+    gene_metadata: String,
+    gene_header: String,
+    anno_records: Vec<AnnotationRecord>,
+    anno_metadata: String,
+    anno_header: String,
+}
+
+fn read_gaf(genes_path: &str, annos_path: &str, lenient: bool, diagnostics: &mut Diagnostics) -> Result<Inputs, String> {
+    let mut gene_reader = open(genes_path, "genes", true)?;
+    let line_offset = gene_reader.line_offset().unwrap_or(0);
+    let gene_records = collect_records(GeneRecord::iter_from(&mut gene_reader), line_offset, lenient, diagnostics)
+        .map_err(|e| format!("failed to parse gene records in {}: {}", genes_path, e))?;
+
+    let mut anno_reader = open(annos_path, "annotations", true)?;
+    let line_offset = anno_reader.line_offset().unwrap_or(0);
+    let gaf_version = anno_reader.gaf_version();
+    let anno_iter = AnnotationRecord::iter_from(&mut anno_reader).gaf_version(gaf_version);
+    let anno_records = collect_records(anno_iter, line_offset, lenient, diagnostics)
+        .map_err(|e| format!("failed to parse annotation records in {}: {}", annos_path, e))?;
+
+    Ok(Inputs {
+        gene_records,
+        gene_metadata: gene_reader.metadata().expect("should capture gene metadata").to_string(),
+        gene_header: gene_reader.header().expect("should get gene headers").to_string(),
+        anno_records,
+        anno_metadata: anno_reader.metadata().expect("should capture annotation metadata").to_string(),
+        anno_header: anno_reader.header().expect("should capture annotation header").to_string(),
+    })
+}
+
+/// Reads GPAD annotations and GPI genes, converting them to their GAF form.
+fn read_gpad(
+    gpad_path: &str,
+    gpi_path: &str,
+    eco: &EcoMapping,
+    lenient: bool,
+    diagnostics: &mut Diagnostics,
+) -> Result<Inputs, String> {
+    let mut gpi_reader = open(gpi_path, "GPI", false)?;
+    let line_offset = gpi_reader.line_offset().unwrap_or(0);
+    let gpi_records = collect_records(GpiRecord::iter_from(&mut gpi_reader), line_offset, lenient, diagnostics)
+        .map_err(|e| format!("failed to parse GPI records in {}: {}", gpi_path, e))?;
+
+    let mut gpad_reader = open(gpad_path, "GPAD", false)?;
+    let line_offset = gpad_reader.line_offset().unwrap_or(0);
+    let gpad_records = collect_records(GpadRecord::iter_from(&mut gpad_reader), line_offset, lenient, diagnostics)
+        .map_err(|e| format!("failed to parse GPAD records in {}: {}", gpad_path, e))?;
 
-    eprintln!("Executing query: {:?}", query);
-    let result = query.execute(&index);
-
-    let mut genes_out = std::fs::File::create(config.genes_out)
-        .map_err(|e| format!("failed to create genes output file: {:?}", e))?;
-    let mut genes_exporter = GafExporter::new(
-        gene_metadata.to_string(),
-        gene_headers.to_string(),
-        result.genes_iter().map(|gene| gene.record));
-    genes_exporter.write_all(&mut genes_out).expect("should write genes file");
-
-    let mut annotations_out = std::fs::File::create(config.annos_out)
-        .map_err(|e| format!("failed to create annotations output file: {:?}", e))?;
-    let mut annotations_exporter = GafExporter::new(
-        anno_metadata.to_string(),
-        anno_headers.to_string(),
-        result.annotations_iter().map(|anno| anno.record));
-    annotations_exporter.write_all(&mut annotations_out)
-        .map_err(|e| format!("failed to export data as GAF: {:?}", e))?;
-
-    Ok(())
+    let gpi_by_id: HashMap<&str, &GpiRecord> = gpi_records.iter()
+        .map(|gpi| (&*gpi.db_object_id, gpi))
+        .collect();
+    let anno_records = gpad_records.iter()
+        .filter_map(|gpad| {
+            let gpi = gpi_by_id.get(&*gpad.db_object_id).copied();
+            AnnotationRecord::from_gpad(gpad, gpi, eco)
+        })
+        .collect();
+
+    Ok(Inputs {
+        gene_records: gpi_records.iter().map(GeneRecord::from_gpi).collect(),
+        gene_metadata: String::new(),
+        gene_header: String::new(),
+        anno_records,
+        anno_metadata: "!gaf-version: 2.2\n".to_string(),
+        anno_header: String::new(),
+    })
+}
+
+/// Reads the mapping between GO evidence codes and ECO classes, using the
+/// built-in mapping if no file is given.
+fn read_eco(mapping_path: Option<&str>, ontology_path: Option<&str>) -> Result<EcoMapping, String> {
+    let mut eco = match mapping_path {
+        Some(path) => EcoMapping::parse_from(decompress(path, "ECO mapping")?)
+            .map_err(|e| format!("failed to parse ECO mapping in {}: {}", path, e))?,
+        None => EcoMapping::default(),
+    };
+    if let Some(path) = ontology_path {
+        let ontology = EcoOntology::parse_from(decompress(path, "ECO ontology")?)
+            .map_err(|e| format!("failed to parse ECO ontology in {}: {}", path, e))?;
+        eco = eco.with_ontology(ontology);
+    }
+    Ok(eco)
+}
+
+/// Opens a (possibly compressed) file for reading.
+fn decompress(path: &str, kind: &str) -> Result<Box<dyn BufRead>, String> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("failed to open {} file: {:?}", kind, e))?;
+    let mut reader = BufReader::new(file);
+    Compression::sniff(&mut reader)
+        .and_then(|compression| compression.decoder(reader))
+        .map_err(|e| format!("failed to read {} file: {:?}", kind, e))
+}
+
+/// Reads the gene names in a gene list, one per line. Blank lines and
+/// lines starting with `#` are skipped, and only the first column of a
+/// tab-separated file is read.
+fn read_gene_list(path: &str) -> Result<Vec<String>, String> {
+    let mut names = Vec::new();
+    for line in decompress(path, "gene list")?.lines() {
+        let line = line.map_err(|e| format!("failed to read gene list: {:?}", e))?;
+        let name = line.split('\t').next().unwrap_or("").trim();
+        if name.is_empty() || name.starts_with('#') {
+            continue;
+        }
+        names.push(name.to_string());
+    }
+    Ok(names)
+}
+
+/// Opens a (possibly compressed) input file and reads its metadata, along
+/// with its header if it has one.
+fn open(path: &str, kind: &str, has_header: bool) -> Result<MetadataReader<Box<dyn BufRead>>, String> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("failed to open {} file: {:?}", kind, e))?;
+    let mut reader = MetadataReader::decompress(BufReader::new(file))
+        .map_err(|e| format!("failed to read {} file: {:?}", kind, e))?;
+    if !has_header {
+        reader = reader.without_header();
+    }
+    reader.read_metadata()
+        .map_err(|e| format!("failed to read {} file: {:?}", kind, e))?;
+    Ok(reader)
+}
+
+/// Collects parsed records, skipping malformed rows into the diagnostics if
+/// parsing leniently. Line numbers are shifted by the given offset.
+fn collect_records<R: Read, T: Record>(
+    records: Records<R, T>,
+    line_offset: u64,
+    lenient: bool,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<T>, ifad::Error> {
+    if lenient {
+        let (records, skipped) = records.lenient()
+            .map_err(|e| e.with_line_offset(line_offset))?;
+        diagnostics.append(skipped.with_line_offset(line_offset));
+        Ok(records)
+    } else {
+        records.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.with_line_offset(line_offset))
+    }
 }
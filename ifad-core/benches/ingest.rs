@@ -28,7 +28,23 @@ fn parse_annotations_benchmark(c: &mut Criterion) {
     c.bench_function("parse annotations 1000 lines", |b| b.iter(|| run_parse_annotations(&data_1000)));
 }
 
+fn run_iter_annotations(data: &str) {
+    let data_reader = Cursor::new(data);
+    let _count = AnnotationRecord::iter_from(data_reader)
+        .filter(|record| record.is_ok())
+        .count();
+}
+
+fn iter_annotations_benchmark(c: &mut Criterion) {
+    let mut reader_1000 = MetadataReader::new(BufReader::new(Cursor::new(ANNOTATIONS_1000)));
+    let mut data_1000 = String::with_capacity(ANNOTATIONS_1000.len());
+    reader_1000.read_to_string(&mut data_1000).unwrap();
+
+    c.bench_function("iterate annotations 1000 lines", |b| b.iter(|| run_iter_annotations(&data_1000)));
+}
+
 criterion_group!(benches,
     metadata_annotations_benchmark,
-    parse_annotations_benchmark);
+    parse_annotations_benchmark,
+    iter_annotations_benchmark);
 criterion_main!(benches);
@@ -0,0 +1,206 @@
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use crate::{Annotation, Gene};
+use crate::error::{Error, ParseError, ParseErrorKind};
+
+/// The columns of an alias file.
+const ALIAS_COLUMNS: &[&str] = &["Gene ID", "Alias"];
+
+/// Maps the names which Genes go by to their IDs, e.g. `ENO1` and `LOS2`
+/// to `AT1G74030` and `AT2G36530`.
+///
+/// Aliases come from the annotations of each Gene (their DB object symbol
+/// and the synonyms of GAF column 11) and from alias files. The ID of a
+/// Gene always names that Gene alone, but an alias may be shared by more
+/// than one Gene, in which case it is ambiguous.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct AliasIndex<'a> {
+    gene_ids: HashSet<&'a str>,
+    aliases: HashMap<String, Vec<&'a str>>,
+}
+
+impl<'a> AliasIndex<'a> {
+    /// Creates an index of the IDs of the Genes, without any aliases.
+    pub fn new(genes: &'a [Gene]) -> AliasIndex<'a> {
+        AliasIndex {
+            gene_ids: genes.iter().map(|gene| gene.gene_id).collect(),
+            aliases: HashMap::new(),
+        }
+    }
+
+    /// Adds an alias of a Gene. Returns false if there is no Gene with the
+    /// ID, in which case the alias is not added.
+    pub fn insert(&mut self, alias: &str, gene_id: &str) -> bool {
+        let gene_id = match self.gene_ids.get(gene_id) {
+            Some(&gene_id) => gene_id,
+            None => return false,
+        };
+        if alias.is_empty() || self.gene_ids.contains(alias) {
+            return true;
+        }
+        // Only allocate the alias the first time it is seen
+        if let Some(gene_ids) = self.aliases.get_mut(alias) {
+            if !gene_ids.contains(&gene_id) {
+                gene_ids.push(gene_id);
+            }
+        } else {
+            self.aliases.insert(alias.to_string(), vec![gene_id]);
+        }
+        true
+    }
+
+    /// Adds the names which annotations give for their Genes. Only the
+    /// annotations which give the ID of a Gene among their names are used.
+    pub fn insert_annotations(&mut self, annotations: &[Annotation]) {
+        for annotation in annotations {
+            let gene_id = match annotation.gene_names.iter().find(|name| self.gene_ids.contains(*name)) {
+                Some(gene_id) => gene_id,
+                None => continue,
+            };
+            for name in annotation.gene_names.iter().chain(Some(&annotation.db_object_symbol)) {
+                self.insert(name, gene_id);
+            }
+        }
+    }
+
+    /// Reads an alias file: tab-separated rows of a gene ID followed by its
+    /// aliases, any of which may list several aliases separated by `|`.
+    /// Blank lines, lines starting with `!` or `#`, and rows for genes which
+    /// are not known (such as a header) are skipped.
+    pub fn read_aliases<R: BufRead>(&mut self, reader: R) -> Result<(), Error> {
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('!') || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+            let (gene_id, aliases) = match &fields[..] {
+                [gene_id, aliases @ ..] if !aliases.is_empty() => (*gene_id, aliases),
+                _ => return Err(ParseError {
+                    line: i as u64 + 1,
+                    column: None,
+                    row: line.to_string(),
+                    cause: ParseErrorKind::ColumnCount { expected: ALIAS_COLUMNS.len(), found: fields.len() },
+                }.into()),
+            };
+            for alias in aliases.iter().flat_map(|aliases| aliases.split('|')) {
+                self.insert(alias.trim(), gene_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// The IDs of every Gene which goes by a name.
+    pub fn resolve(&self, name: &str) -> &[&'a str] {
+        if let Some(gene_id) = self.gene_ids.get(name) {
+            return std::slice::from_ref(gene_id);
+        }
+        self.aliases.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// The ID of the Gene which goes by a name, or None if no Gene or more
+    /// than one Gene does.
+    pub fn gene_id(&self, name: &str) -> Option<&'a str> {
+        match self.resolve(name) {
+            [gene_id] => Some(gene_id),
+            _ => None,
+        }
+    }
+
+    /// The ID of the Gene an annotation annotates. A name of the annotation
+    /// which is the ID of a Gene is used first, and otherwise the first of
+    /// its names or its symbol which is an alias of only one Gene.
+    pub fn gene_id_of(&self, annotation: &Annotation) -> Option<&'a str> {
+        annotation.gene_names.iter()
+            .find_map(|name| self.gene_ids.get(*name).copied())
+            .or_else(|| annotation.gene_names.iter()
+                .chain(Some(&annotation.db_object_symbol))
+                .find_map(|name| self.gene_id(name)))
+    }
+
+    /// The aliases which are shared by more than one Gene, with the IDs of
+    /// those Genes, ordered by alias.
+    pub fn ambiguous(&self) -> Vec<(&str, &[&'a str])> {
+        let mut ambiguous: Vec<(&str, &[&'a str])> = self.aliases.iter()
+            .filter(|(_, gene_ids)| gene_ids.len() > 1)
+            .map(|(alias, gene_ids)| (alias.as_str(), gene_ids.as_slice()))
+            .collect();
+        ambiguous.sort_unstable();
+        ambiguous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnnotationRecord, Aspect, EvidencePolicy, GeneRecord};
+
+    fn record(gene: &str, symbol: &str, synonyms: &str) -> AnnotationRecord {
+        AnnotationRecord {
+            db: "TAIR".to_string(),
+            database_id: "locus:2031476".to_string(),
+            db_object_symbol: symbol.to_string(),
+            invert: "".to_string(),
+            go_term: "GO:0004634".to_string(),
+            reference: "TAIR:Communication:1345790".to_string(),
+            evidence_code: "IDA".to_string(),
+            additional_evidence: "".to_string(),
+            aspect: Aspect::MolecularFunction,
+            unique_gene_name: gene.to_string(),
+            alternative_gene_name: synonyms.to_string(),
+            gene_product_type: "protein".to_string(),
+            taxon: "taxon:3702".to_string(),
+            date: "20190907".to_string(),
+            assigned_by: "TAIR".to_string(),
+            annotation_extension: "".to_string(),
+            gene_product_form_id: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_alias_index() {
+        let gene_records: Vec<GeneRecord> = ["AT1G74030", "AT2G36530", "AT2G29560"].iter()
+            .map(|gene_id| GeneRecord {
+                gene_id: gene_id.to_string(),
+                gene_product_type: "protein".to_string(),
+            })
+            .collect();
+        let genes: Vec<Gene> = gene_records.iter().map(Gene::from_record).collect();
+        let anno_records = vec![
+            record("AT1G74030", "ENO1", "AT1G74030|F2P24.12|ENO"),
+            record("AT2G36530", "LOS2", "AT2G36530|ENO2|ENO"),
+            // Names no gene ID, but its symbol is an alias from the file
+            record("F3G5.1", "ENOC", ""),
+            // Only names an ambiguous alias
+            record("ENO", "", ""),
+        ];
+        let annotations: Vec<Annotation> = anno_records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .collect();
+
+        let mut aliases = AliasIndex::new(&genes);
+        aliases.insert_annotations(&annotations);
+        let alias_file = "locus_name\tsymbol\tfull_name\n\
+                          # cytosolic enolase\n\
+                          AT2G29560\tENOC|CYTOSOLIC ENOLASE\n\
+                          AT9G99999\tUNKNOWN\n";
+        aliases.read_aliases(alias_file.as_bytes()).unwrap();
+
+        assert_eq!(aliases.resolve("ENO1"), &["AT1G74030"]);
+        assert_eq!(aliases.resolve("F2P24.12"), &["AT1G74030"]);
+        assert_eq!(aliases.resolve("AT2G36530"), &["AT2G36530"]);
+        assert_eq!(aliases.gene_id("CYTOSOLIC ENOLASE"), Some("AT2G29560"));
+        assert!(aliases.resolve("UNKNOWN").is_empty());
+        assert_eq!(aliases.gene_id("ENO"), None);
+        assert_eq!(aliases.ambiguous(), vec![("ENO", &["AT1G74030", "AT2G36530"][..])]);
+
+        let gene_ids: Vec<Option<&str>> = annotations.iter()
+            .map(|annotation| aliases.gene_id_of(annotation))
+            .collect();
+        assert_eq!(gene_ids, vec![Some("AT1G74030"), Some("AT2G36530"), Some("AT2G29560"), None]);
+
+        let error = aliases.read_aliases("AT1G74030\n".as_bytes()).unwrap_err();
+        assert!(matches!(error, Error::Parse(ParseError { line: 1, .. })));
+    }
+}
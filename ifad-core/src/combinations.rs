@@ -0,0 +1,250 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::Write;
+use serde::Serialize;
+use crate::{AnnotationStatus, Aspect, Gene, Index, NegationPolicy};
+
+/// The statuses a Gene may have for an Aspect, from the most to the least
+/// informative.
+const COMBINATION_STATUSES: &[AnnotationStatus] = &[
+    AnnotationStatus::KnownExperimental,
+    AnnotationStatus::KnownOther,
+    AnnotationStatus::Unknown,
+    AnnotationStatus::Negated,
+    AnnotationStatus::Unannotated,
+];
+
+/// The status of a Gene for each of the three Aspects.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize)]
+pub struct StatusCombination {
+    #[serde(rename = "F")]
+    pub function: AnnotationStatus,
+    #[serde(rename = "P")]
+    pub process: AnnotationStatus,
+    #[serde(rename = "C")]
+    pub component: AnnotationStatus,
+}
+
+impl StatusCombination {
+    pub fn status(&self, aspect: Aspect) -> AnnotationStatus {
+        match aspect {
+            Aspect::MolecularFunction => self.function,
+            Aspect::BiologicalProcess => self.process,
+            Aspect::CellularComponent => self.component,
+        }
+    }
+}
+
+/// The number of Genes with one combination of statuses.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+pub struct CombinationCount {
+    #[serde(flatten)]
+    pub combination: StatusCombination,
+    pub genes: usize,
+}
+
+/// The number of Genes with each combination of statuses across the three
+/// Aspects, such as for an UpSet plot.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct CombinationMatrix {
+    /// The number of Genes in the Index.
+    pub genes: usize,
+    /// Every combination of statuses, including those without any Genes,
+    /// ordered by the status for F, then P, then C.
+    pub combinations: Vec<CombinationCount>,
+}
+
+impl Index<'_, '_> {
+    /// The status of a Gene for each Aspect.
+    ///
+    /// A Gene may have more than one status for an Aspect in the
+    /// `gene_index` (e.g. both EXP and UNKNOWN), in which case it is given
+    /// the most informative one: EXP, then OTHER, UNKNOWN, NEGATED, and
+    /// finally UNANNOTATED.
+    pub fn status_combination(&self, gene: &Gene) -> StatusCombination {
+        let status = |aspect| {
+            let by_status = self.gene_index.get(&aspect);
+            COMBINATION_STATUSES.iter().copied()
+                .find(|status| by_status
+                    .and_then(|by_status| by_status.get(status))
+                    .is_some_and(|genes| genes.contains(gene)))
+                .unwrap_or(AnnotationStatus::Unannotated)
+        };
+        StatusCombination {
+            function: status(Aspect::MolecularFunction),
+            process: status(Aspect::BiologicalProcess),
+            component: status(Aspect::CellularComponent),
+        }
+    }
+
+    /// Counts the Genes with each combination of statuses across the three
+    /// Aspects, as given by `status_combination`.
+    ///
+    /// There are 4 × 4 × 4 combinations, or 5 × 5 × 5 when `NOT`
+    /// annotations are classified as Negated.
+    pub fn status_combinations(&self) -> CombinationMatrix {
+        let mut by_combination: HashMap<StatusCombination, usize> = HashMap::new();
+        for (gene, _) in self.anno_index.values() {
+            *by_combination.entry(self.status_combination(gene)).or_default() += 1;
+        }
+
+        let statuses: Vec<AnnotationStatus> = [
+            AnnotationStatus::KnownExperimental,
+            AnnotationStatus::KnownOther,
+            AnnotationStatus::Unknown,
+            AnnotationStatus::Unannotated,
+            AnnotationStatus::Negated,
+        ].iter().copied()
+            .filter(|&status| status != AnnotationStatus::Negated || self.options.negation == NegationPolicy::Separate)
+            .collect();
+
+        let mut combinations = Vec::new();
+        for &function in &statuses {
+            for &process in &statuses {
+                for &component in &statuses {
+                    let combination = StatusCombination { function, process, component };
+                    let genes = by_combination.get(&combination).copied().unwrap_or(0);
+                    combinations.push(CombinationCount { combination, genes });
+                }
+            }
+        }
+
+        CombinationMatrix { genes: self.anno_index.len(), combinations }
+    }
+}
+
+impl CombinationMatrix {
+    /// The number of Genes with a combination of statuses.
+    pub fn get(&self, combination: &StatusCombination) -> usize {
+        self.combinations.iter()
+            .find(|count| &count.combination == combination)
+            .map_or(0, |count| count.genes)
+    }
+
+    /// The combinations which have any Genes, from the most Genes to the
+    /// fewest.
+    pub fn most_populated(&self) -> Vec<CombinationCount> {
+        let mut combinations: Vec<CombinationCount> = self.combinations.iter()
+            .filter(|count| count.genes > 0)
+            .copied()
+            .collect();
+        combinations.sort_by_key(|count| Reverse(count.genes));
+        combinations
+    }
+
+    /// Writes the most populated combinations as a table aligned for
+    /// reading in a terminal.
+    pub fn write_table<W: Write>(&self, mut writer: W, limit: usize) -> std::io::Result<()> {
+        writeln!(writer, "{:<11}  {:<11}  {:<11}  {:>10}  {:>7}", "F", "P", "C", "Genes", "%")?;
+        for count in self.most_populated().into_iter().take(limit) {
+            let percentage = match self.genes {
+                0 => 0.0,
+                total => 100.0 * count.genes as f64 / total as f64,
+            };
+            writeln!(writer, "{:<11}  {:<11}  {:<11}  {:>10}  {:>6.1}%",
+                     count.combination.function.as_str(),
+                     count.combination.process.as_str(),
+                     count.combination.component.as_str(),
+                     count.genes,
+                     percentage)?;
+        }
+        Ok(())
+    }
+
+    /// Writes every combination as a tab-separated table.
+    pub fn write_tsv<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, "F\tP\tC\tGenes")?;
+        for count in &self.combinations {
+            writeln!(writer, "{}\t{}\t{}\t{}",
+                     count.combination.function.as_str(),
+                     count.combination.process.as_str(),
+                     count.combination.component.as_str(),
+                     count.genes)?;
+        }
+        Ok(())
+    }
+
+    /// Writes every combination as a JSON object.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Annotation, AnnotationRecord, EvidencePolicy, GeneRecord};
+    use AnnotationStatus::*;
+
+    fn record(gene: &str, aspect: Aspect, evidence_code: &str) -> AnnotationRecord {
+        AnnotationRecord {
+            db: "TAIR".to_string(),
+            database_id: "locus:2031476".to_string(),
+            db_object_symbol: "".to_string(),
+            invert: "".to_string(),
+            go_term: "GO:0005634".to_string(),
+            reference: "TAIR:Communication:1345790".to_string(),
+            evidence_code: evidence_code.to_string(),
+            additional_evidence: "".to_string(),
+            aspect,
+            unique_gene_name: gene.to_string(),
+            alternative_gene_name: "".to_string(),
+            gene_product_type: "protein".to_string(),
+            taxon: "taxon:3702".to_string(),
+            date: "20190907".to_string(),
+            assigned_by: "TAIR".to_string(),
+            annotation_extension: "".to_string(),
+            gene_product_form_id: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_status_combinations() {
+        let gene_records: Vec<GeneRecord> = ["AT1G01010", "AT1G01020", "AT1G01030"].iter()
+            .map(|gene_id| GeneRecord {
+                gene_id: gene_id.to_string(),
+                gene_product_type: "protein".to_string(),
+            })
+            .collect();
+        let genes: Vec<Gene> = gene_records.iter().map(Gene::from_record).collect();
+        let anno_records = vec![
+            // Both EXP and UNKNOWN for F, which counts as EXP
+            record("AT1G01010", Aspect::MolecularFunction, "IDA"),
+            record("AT1G01010", Aspect::MolecularFunction, "ND"),
+            record("AT1G01010", Aspect::CellularComponent, "IEA"),
+            record("AT1G01020", Aspect::MolecularFunction, "IMP"),
+            record("AT1G01020", Aspect::CellularComponent, "ISS"),
+        ];
+        let annotations: Vec<Annotation> = anno_records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .collect();
+        let index = Index::new(&genes, &annotations);
+
+        let combination = StatusCombination { function: KnownExperimental, process: Unannotated, component: KnownOther };
+        assert_eq!(index.status_combination(&genes[0]), combination);
+        assert_eq!(combination.status(Aspect::CellularComponent), KnownOther);
+
+        let matrix = index.status_combinations();
+        assert_eq!(matrix.combinations.len(), 64);
+        assert_eq!(matrix.get(&combination), 2);
+        let unannotated = StatusCombination { function: Unannotated, process: Unannotated, component: Unannotated };
+        assert_eq!(matrix.get(&unannotated), 1);
+        let most_populated = matrix.most_populated();
+        assert_eq!(most_populated, vec![
+            CombinationCount { combination, genes: 2 },
+            CombinationCount { combination: unannotated, genes: 1 },
+        ]);
+
+        let mut tsv = Vec::new();
+        matrix.write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert_eq!(tsv.lines().next(), Some("F\tP\tC\tGenes"));
+        assert!(tsv.contains("\nEXP\tUNANNOTATED\tOTHER\t2\n"));
+
+        let mut json = Vec::new();
+        matrix.write_json(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["combinations"][0], serde_json::json!({ "F": "EXP", "P": "EXP", "C": "EXP", "genes": 0 }));
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// A compression format that Gene and Annotation files may be stored in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Zstd,
+}
+
+impl Compression {
+
+    /// Identifies the compression format of some data by its magic bytes.
+    pub fn detect(bytes: &[u8]) -> Compression {
+        match bytes {
+            [0x1f, 0x8b, ..] => Compression::Gzip,
+            [b'B', b'Z', b'h', ..] => Compression::Bzip2,
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Identifies the compression format of a reader without consuming
+    /// any of its data.
+    pub fn sniff<R: BufRead>(reader: &mut R) -> std::io::Result<Compression> {
+        let bytes = reader.fill_buf()?;
+        Ok(Compression::detect(bytes))
+    }
+
+    /// Chooses a compression format based on a file's extension, e.g. for
+    /// deciding how to compress an output file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Compression {
+        let extension = path.as_ref().extension().and_then(|ext| ext.to_str());
+        match extension {
+            Some("gz") => Compression::Gzip,
+            Some("bz2") => Compression::Bzip2,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Wraps a reader of data in this format so that it reads decompressed data.
+    pub fn decoder<'a, R: BufRead + 'a>(self, reader: R) -> std::io::Result<Box<dyn BufRead + 'a>> {
+        let decoder: Box<dyn BufRead> = match self {
+            Compression::None => Box::new(reader),
+            Compression::Gzip => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))),
+            Compression::Bzip2 => Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader))),
+            Compression::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+        };
+        Ok(decoder)
+    }
+
+    /// Compresses everything written by `write_data` into `writer`.
+    ///
+    /// The compressed stream is finished before returning, so that any
+    /// errors writing the end of the stream are not lost.
+    pub(crate) fn encode<W, F>(self, writer: W, write_data: F) -> std::io::Result<()>
+        where W: Write,
+              F: FnOnce(&mut dyn Write) -> std::io::Result<()>,
+    {
+        match self {
+            Compression::None => {
+                let mut writer = writer;
+                write_data(&mut writer)?;
+            }
+            Compression::Gzip => {
+                let level = flate2::Compression::default();
+                let mut encoder = flate2::write::GzEncoder::new(writer, level);
+                write_data(&mut encoder)?;
+                encoder.finish()?;
+            }
+            Compression::Bzip2 => {
+                let level = bzip2::Compression::default();
+                let mut encoder = bzip2::write::BzEncoder::new(writer, level);
+                write_data(&mut encoder)?;
+                encoder.finish()?;
+            }
+            Compression::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(writer, 0)?;
+                write_data(&mut encoder)?;
+                encoder.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn test_compression_round_trip() {
+        let input = "!gaf-version: 2.1\nTAIR\tlocus:2031476\tENO1\n";
+
+        for &compression in &[Compression::None, Compression::Gzip, Compression::Bzip2, Compression::Zstd] {
+            let mut compressed = Vec::new();
+            compression.encode(&mut compressed, |writer| writer.write_all(input.as_bytes()))
+                .expect("should compress data");
+
+            let mut reader = Cursor::new(&compressed);
+            assert_eq!(Compression::sniff(&mut reader).unwrap(), compression);
+
+            let mut output = String::new();
+            compression.decoder(reader).unwrap().read_to_string(&mut output).unwrap();
+            assert_eq!(output, input);
+        }
+    }
+
+    #[test]
+    fn test_compression_from_path() {
+        assert_eq!(Compression::from_path("tair.gaf.gz"), Compression::Gzip);
+        assert_eq!(Compression::from_path("tair.gaf.bz2"), Compression::Bzip2);
+        assert_eq!(Compression::from_path("tair.gaf.zst"), Compression::Zstd);
+        assert_eq!(Compression::from_path("tair.gaf"), Compression::None);
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+use crate::{Annotation, Aspect, ParseError};
+
+/// Describes a piece of input which was dropped rather than used, or which
+/// disagrees with (or was changed to agree with) the ontology.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Diagnostic {
+    /// A row which could not be parsed and was skipped.
+    SkippedRow(ParseError),
+    /// An annotation whose gene could not be found among the known Genes.
+    UnknownGene {
+        gene_name: String,
+        go_term: String,
+    },
+    /// An annotation whose Aspect disagrees with the namespace of its GO
+    /// term, which implies the `expected` Aspect.
+    AspectMismatch {
+        gene_name: String,
+        go_term: String,
+        aspect: Aspect,
+        expected: Aspect,
+    },
+    /// An annotation to a GO term which is obsolete.
+    ObsoleteTerm {
+        gene_name: String,
+        go_term: String,
+        replaced_by: Option<String>,
+    },
+    /// An annotation whose GO term was rewritten from an alternative or
+    /// obsolete ID to the primary ID `go_term`.
+    RemappedTerm {
+        gene_name: String,
+        original_term: String,
+        go_term: String,
+    },
+    /// An alias which is shared by more than one Gene, and so is not used
+    /// to match annotations to Genes.
+    AmbiguousAlias {
+        alias: String,
+        gene_ids: Vec<String>,
+    },
+}
+
+impl Diagnostic {
+    pub fn unknown_gene(annotation: &Annotation) -> Diagnostic {
+        Diagnostic::UnknownGene {
+            gene_name: annotation.record.unique_gene_name.to_string(),
+            go_term: annotation.go_term.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Diagnostic::SkippedRow(error) => write!(f, "skipped row: {}", error),
+            Diagnostic::UnknownGene { gene_name, go_term } => {
+                write!(f, "skipped annotation of {} to unknown gene {}", go_term, gene_name)
+            }
+            Diagnostic::AspectMismatch { gene_name, go_term, aspect, expected } => {
+                write!(f, "annotation of {} to {} has aspect {:?}, but the term belongs to {:?}",
+                       gene_name, go_term, aspect, expected)
+            }
+            Diagnostic::ObsoleteTerm { gene_name, go_term, replaced_by: Some(replaced_by) } => {
+                write!(f, "annotation of {} to obsolete term {}, which is replaced by {}",
+                       gene_name, go_term, replaced_by)
+            }
+            Diagnostic::ObsoleteTerm { gene_name, go_term, replaced_by: None } => {
+                write!(f, "annotation of {} to obsolete term {}", gene_name, go_term)
+            }
+            Diagnostic::RemappedTerm { gene_name, original_term, go_term } => {
+                write!(f, "remapped annotation of {} from {} to {}", gene_name, original_term, go_term)
+            }
+            Diagnostic::AmbiguousAlias { alias, gene_ids } => {
+                write!(f, "alias {} is shared by genes {}", alias, gene_ids.join(", "))
+            }
+        }
+    }
+}
+
+/// A report of everything that was dropped while reading data and
+/// building an Index.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Moves all of the diagnostics from `other` into this report.
+    pub fn append(&mut self, mut other: Diagnostics) {
+        self.diagnostics.append(&mut other.diagnostics);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn skipped_rows(&self) -> impl Iterator<Item=&ParseError> {
+        self.diagnostics.iter().filter_map(|diagnostic| match diagnostic {
+            Diagnostic::SkippedRow(error) => Some(error),
+            _ => None,
+        })
+    }
+
+    /// Shifts the line numbers of all skipped rows by the given offset.
+    ///
+    /// See `Error::with_line_offset`.
+    pub fn with_line_offset(mut self, offset: u64) -> Self {
+        for diagnostic in &mut self.diagnostics {
+            if let Diagnostic::SkippedRow(error) = diagnostic {
+                error.line += offset;
+            }
+        }
+        self
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    /// Summarizes the number of each kind of diagnostic in the report.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let skipped_rows = self.skipped_rows().count();
+        let unknown_genes = self.iter()
+            .filter(|diagnostic| matches!(diagnostic, Diagnostic::UnknownGene { .. }))
+            .count();
+        write!(f, "skipped {} malformed rows and {} annotations to unknown genes",
+               skipped_rows, unknown_genes)?;
+
+        let mismatches = self.iter()
+            .filter(|diagnostic| matches!(diagnostic, Diagnostic::AspectMismatch { .. }))
+            .count();
+        let obsolete = self.iter()
+            .filter(|diagnostic| matches!(diagnostic, Diagnostic::ObsoleteTerm { .. }))
+            .count();
+        if mismatches + obsolete > 0 {
+            write!(f, ", and found {} annotations with the wrong aspect and {} to obsolete terms",
+                   mismatches, obsolete)?;
+        }
+
+        let remapped = self.iter()
+            .filter(|diagnostic| matches!(diagnostic, Diagnostic::RemappedTerm { .. }))
+            .count();
+        if remapped > 0 {
+            write!(f, ", and remapped {} annotations to primary term IDs", remapped)?;
+        }
+
+        let ambiguous = self.iter()
+            .filter(|diagnostic| matches!(diagnostic, Diagnostic::AmbiguousAlias { .. }))
+            .count();
+        if ambiguous > 0 {
+            write!(f, ", and found {} aliases shared by more than one gene", ambiguous)?;
+        }
+        Ok(())
+    }
+}
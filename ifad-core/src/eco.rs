@@ -0,0 +1,332 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::BufRead;
+use crate::error::{Error, ParseError, ParseErrorKind};
+use crate::obo;
+
+/// The ECO class which stands in for each GO evidence code by default,
+/// from the GO Consortium's `gaf-eco-mapping.txt`.
+const DEFAULT_MAPPING: &[(&str, &str)] = &[
+    ("EXP", "ECO:0000269"),
+    ("IDA", "ECO:0000314"),
+    ("IPI", "ECO:0000353"),
+    ("IMP", "ECO:0000315"),
+    ("IGI", "ECO:0000316"),
+    ("IEP", "ECO:0000270"),
+    ("HTP", "ECO:0006056"),
+    ("HDA", "ECO:0007005"),
+    ("HMP", "ECO:0007001"),
+    ("HGI", "ECO:0007003"),
+    ("HEP", "ECO:0007007"),
+    ("IBA", "ECO:0000318"),
+    ("IBD", "ECO:0000319"),
+    ("IKR", "ECO:0000320"),
+    ("IRD", "ECO:0000321"),
+    ("ISS", "ECO:0000250"),
+    ("ISO", "ECO:0000266"),
+    ("ISA", "ECO:0000247"),
+    ("ISM", "ECO:0000255"),
+    ("IGC", "ECO:0000317"),
+    ("RCA", "ECO:0000245"),
+    ("TAS", "ECO:0000304"),
+    ("NAS", "ECO:0000303"),
+    ("IC", "ECO:0000305"),
+    ("ND", "ECO:0000307"),
+    ("IEA", "ECO:0000501"),
+];
+
+/// The columns of a `gaf-eco-mapping.txt` file.
+const MAPPING_COLUMNS: &[&str] = &["GO Evidence Code", "Reference", "ECO ID"];
+
+/// Translates between GO evidence codes, as used in GAF files, and the
+/// ECO classes which GPAD files use in their place.
+///
+/// An evidence code usually stands for one ECO class, but some references
+/// (such as the GO_REFs of particular electronic annotation pipelines) use
+/// a more specific class. Given the ECO ontology, classes without a code
+/// of their own take the code of their nearest ancestor which has one.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EcoMapping {
+    eco_classes: HashMap<String, String>,
+    reference_classes: HashMap<(String, String), String>,
+    evidence_codes: HashMap<String, String>,
+    ontology: Option<EcoOntology>,
+}
+
+impl EcoMapping {
+    /// Reads a mapping in the format of the GO Consortium's
+    /// `gaf-eco-mapping.txt`: tab-separated rows of an evidence code, a
+    /// reference (or `Default`), and an ECO class.
+    pub fn parse_from<R: BufRead>(reader: R) -> Result<EcoMapping, Error> {
+        let mut mapping = EcoMapping {
+            eco_classes: HashMap::new(),
+            reference_classes: HashMap::new(),
+            evidence_codes: HashMap::new(),
+            ontology: None,
+        };
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+            let (code, reference, eco) = match fields[..] {
+                [code, reference, eco, ..] => (code, reference, eco),
+                _ => return Err(ParseError {
+                    line: i as u64 + 1,
+                    column: None,
+                    row: line.to_string(),
+                    cause: ParseErrorKind::ColumnCount { expected: MAPPING_COLUMNS.len(), found: fields.len() },
+                }.into()),
+            };
+            if !eco.starts_with("ECO:") {
+                return Err(ParseError {
+                    line: i as u64 + 1,
+                    column: Some(MAPPING_COLUMNS[2]),
+                    row: line.to_string(),
+                    cause: ParseErrorKind::InvalidValue(format!("'{}' is not an ECO class", eco)),
+                }.into());
+            }
+
+            if reference == "Default" {
+                mapping.eco_classes.insert(code.to_string(), eco.to_string());
+            } else {
+                mapping.reference_classes.insert((code.to_string(), reference.to_string()), eco.to_string());
+            }
+
+            // The first code given for a class wins, e.g. IKR rather than IMR
+            mapping.evidence_codes.entry(eco.to_string()).or_insert_with(|| code.to_string());
+        }
+        Ok(mapping)
+    }
+
+    /// Uses the ECO ontology to find codes for classes which are not in
+    /// the mapping, and to relate classes to each other.
+    pub fn with_ontology(mut self, ontology: EcoOntology) -> Self {
+        self.ontology = Some(ontology);
+        self
+    }
+
+    pub fn ontology(&self) -> Option<&EcoOntology> {
+        self.ontology.as_ref()
+    }
+
+    /// The GO evidence code for an ECO class, if the class or one of its
+    /// ancestors has one.
+    pub fn evidence_code(&self, eco_class: &str) -> Option<&str> {
+        if let Some(code) = self.evidence_codes.get(eco_class) {
+            return Some(code);
+        }
+        let ontology = self.ontology.as_ref()?;
+        ontology.ancestors(eco_class).into_iter()
+            .find_map(|ancestor| self.evidence_codes.get(ancestor))
+            .map(String::as_str)
+    }
+
+    /// The ECO class which stands in for a GO evidence code.
+    pub fn eco_class(&self, evidence_code: &str) -> Option<&str> {
+        self.eco_classes.get(evidence_code).map(String::as_str)
+    }
+
+    /// The ECO class which stands in for a GO evidence code when it is
+    /// used with the given references (e.g. `PMID:1234|GO_REF:0000002`),
+    /// preferring a class specific to one of the references.
+    pub fn eco_class_for(&self, evidence_code: &str, references: &str) -> Option<&str> {
+        references.split('|')
+            .find_map(|reference| {
+                self.reference_classes.get(&(evidence_code.to_string(), reference.to_string()))
+            })
+            .map(String::as_str)
+            .or_else(|| self.eco_class(evidence_code))
+    }
+
+    /// Every GO evidence code and ECO class known to the mapping or its
+    /// ontology.
+    pub fn evidence(&self) -> HashSet<&str> {
+        let mut evidence: HashSet<&str> = HashSet::new();
+        for (code, eco) in &self.eco_classes {
+            evidence.extend([code.as_str(), eco.as_str()]);
+        }
+        for ((code, _), eco) in &self.reference_classes {
+            evidence.extend([code.as_str(), eco.as_str()]);
+        }
+        for (eco, code) in &self.evidence_codes {
+            evidence.extend([eco.as_str(), code.as_str()]);
+        }
+        if let Some(ontology) = &self.ontology {
+            evidence.extend(ontology.classes());
+        }
+        evidence
+    }
+
+    /// Whether some evidence (a GO evidence code or an ECO class) falls
+    /// under a class of evidence (also a GO evidence code or ECO class).
+    ///
+    /// Evidence falls under an ECO class if its own ECO class is that class
+    /// or one of its descendants, and under an evidence code if its own
+    /// code is that code.
+    pub fn is_a(&self, evidence: &str, class: &str) -> bool {
+        if evidence == class {
+            return true;
+        }
+
+        let eco = if evidence.starts_with("ECO:") { Some(evidence) } else { self.eco_class(evidence) };
+        let eco = match eco {
+            Some(eco) => eco,
+            None => return false,
+        };
+
+        if class.starts_with("ECO:") {
+            eco == class || self.ontology.as_ref()
+                .map(|ontology| ontology.ancestors(eco).contains(&class))
+                .unwrap_or(false)
+        } else {
+            self.evidence_code(eco) == Some(class)
+        }
+    }
+}
+
+impl Default for EcoMapping {
+    fn default() -> Self {
+        let eco_classes = DEFAULT_MAPPING.iter()
+            .map(|&(code, eco)| (code.to_string(), eco.to_string()))
+            .collect();
+        let evidence_codes = DEFAULT_MAPPING.iter()
+            .map(|&(code, eco)| (eco.to_string(), code.to_string()))
+            .collect();
+        EcoMapping {
+            eco_classes,
+            reference_classes: HashMap::new(),
+            evidence_codes,
+            ontology: None,
+        }
+    }
+}
+
+/// The `is_a` hierarchy of the Evidence and Conclusion Ontology.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct EcoOntology {
+    parents: HashMap<String, Vec<String>>,
+}
+
+impl EcoOntology {
+    /// Reads the ECO classes and their `is_a` parents from an OBO file,
+    /// such as `eco.obo` or a subset of it.
+    pub fn parse_from<R: BufRead>(reader: R) -> Result<EcoOntology, Error> {
+        let parents = obo::parse_stanzas(reader)?.into_iter()
+            .filter(|stanza| stanza.kind == "Term")
+            .filter_map(|stanza| {
+                let id = stanza.get("id")?.to_string();
+                let parents = stanza.get_all("is_a").map(str::to_string).collect();
+                Some((id, parents))
+            })
+            .collect();
+        Ok(EcoOntology { parents })
+    }
+
+    pub fn contains(&self, eco_class: &str) -> bool {
+        self.parents.contains_key(eco_class)
+    }
+
+    /// Every ECO class in the ontology.
+    pub fn classes(&self) -> impl Iterator<Item=&str> {
+        self.parents.keys().map(String::as_str)
+    }
+
+    /// Every ancestor of an ECO class, nearest first.
+    pub fn ancestors<'a>(&'a self, eco_class: &str) -> Vec<&'a str> {
+        let mut ancestors = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<&str> = self.parents.get(eco_class)
+            .into_iter().flatten().map(String::as_str).collect();
+        while let Some(ancestor) = queue.pop_front() {
+            if !seen.insert(ancestor) { continue; }
+            ancestors.push(ancestor);
+            queue.extend(self.parents.get(ancestor).into_iter().flatten().map(String::as_str));
+        }
+        ancestors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const ECO_OBO: &str = r"format-version: 1.2
+
+[Term]
+id: ECO:0000000
+name: evidence
+
+[Term]
+id: ECO:0000006
+name: experimental evidence
+is_a: ECO:0000000 ! evidence
+
+[Term]
+id: ECO:0000269
+name: experimental evidence used in manual assertion
+is_a: ECO:0000006 ! experimental evidence
+
+[Term]
+id: ECO:0000314
+name: direct assay evidence used in manual assertion
+is_a: ECO:0000269 ! experimental evidence used in manual assertion
+
+[Term]
+id: ECO:0007634
+name: immunofluorescence evidence used in manual assertion
+is_a: ECO:0000314 ! direct assay evidence used in manual assertion
+
+[Term]
+id: ECO:0000501
+name: evidence used in automatic assertion
+is_a: ECO:0000000 ! evidence
+";
+
+    #[test]
+    fn test_default_mapping() {
+        let mapping = EcoMapping::default();
+        assert_eq!(mapping.evidence_code("ECO:0000314"), Some("IDA"));
+        assert_eq!(mapping.evidence_code("ECO:0000501"), Some("IEA"));
+        assert_eq!(mapping.evidence_code("ECO:9999999"), None);
+        assert_eq!(mapping.eco_class("EXP"), Some("ECO:0000269"));
+        assert_eq!(mapping.eco_class("XYZ"), None);
+    }
+
+    #[test]
+    fn test_parse_mapping() {
+        let file = "# GO evidence code to ECO mapping
+IEA\tDefault\tECO:0000501
+IEA\tGO_REF:0000002\tECO:0000256
+IKR\tDefault\tECO:0000320
+IMR\tDefault\tECO:0000320
+";
+        let mapping = EcoMapping::parse_from(Cursor::new(file)).unwrap();
+        assert_eq!(mapping.eco_class("IEA"), Some("ECO:0000501"));
+        assert_eq!(mapping.eco_class_for("IEA", "PMID:1|GO_REF:0000002"), Some("ECO:0000256"));
+        assert_eq!(mapping.eco_class_for("IEA", "GO_REF:0000003"), Some("ECO:0000501"));
+        assert_eq!(mapping.evidence_code("ECO:0000256"), Some("IEA"));
+        assert_eq!(mapping.evidence_code("ECO:0000320"), Some("IKR"));
+
+        let error = EcoMapping::parse_from(Cursor::new("IEA\tDefault\n")).unwrap_err();
+        assert!(matches!(error, Error::Parse(ParseError { line: 1, .. })));
+    }
+
+    #[test]
+    fn test_eco_ancestry() {
+        let ontology = EcoOntology::parse_from(Cursor::new(ECO_OBO)).unwrap();
+        assert_eq!(ontology.ancestors("ECO:0007634"), vec!["ECO:0000314", "ECO:0000269", "ECO:0000006", "ECO:0000000"]);
+
+        let mapping = EcoMapping::default().with_ontology(ontology);
+        // A class without a code of its own takes its nearest ancestor's code
+        assert_eq!(mapping.evidence_code("ECO:0007634"), Some("IDA"));
+        assert!(mapping.is_a("ECO:0007634", "IDA"));
+        assert!(mapping.is_a("ECO:0007634", "ECO:0000006"));
+        assert!(mapping.is_a("IDA", "ECO:0000269"));
+        assert!(!mapping.is_a("IEA", "ECO:0000006"));
+        assert!(!mapping.is_a("IEA", "IDA"));
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashSet;
+use std::io::Write;
+use crate::{Aspect, Gene, QueryResult, TermIndex};
+
+/// The over-representation of a GO term among a study set of genes, compared
+/// to the population of all genes in an Index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enrichment {
+    pub term: String,
+    pub name: String,
+    pub aspect: Option<Aspect>,
+    /// The number of study genes annotated to the term or its descendants.
+    pub study_genes: usize,
+    pub study_total: usize,
+    /// The number of population genes annotated to the term or its
+    /// descendants.
+    pub population_genes: usize,
+    pub population_total: usize,
+    /// The probability of finding at least `study_genes` annotated genes in
+    /// a random study set of the same size, from the hypergeometric
+    /// distribution (i.e. a one-sided Fisher's exact test).
+    pub p_value: f64,
+    /// The p-value with a Bonferroni correction for the number of terms.
+    pub bonferroni: f64,
+    /// The false discovery rate of the term by the Benjamini-Hochberg
+    /// procedure.
+    pub benjamini_hochberg: f64,
+}
+
+impl Enrichment {
+    /// How many times more often study genes are annotated to the term than
+    /// population genes are, or 0 if the study set is empty.
+    pub fn fold_enrichment(&self) -> f64 {
+        match (self.study_total, self.population_genes) {
+            (0, _) | (_, 0) => 0.0,
+            _ => {
+                let study = self.study_genes as f64 / self.study_total as f64;
+                let population = self.population_genes as f64 / self.population_total as f64;
+                study / population
+            }
+        }
+    }
+}
+
+impl TermIndex<'_, '_> {
+    /// Tests each GO term for over-representation among the genes of a
+    /// query result, using every gene in the Index as the population.
+    ///
+    /// Genes count as annotated to a term if they are annotated to it or
+    /// to any of its descendants, as by `TermIndex::genes`. Every term with
+    /// at least one annotated gene in the population is tested, and the
+    /// results are sorted from the smallest p-value. Study genes which are
+    /// not in the Index are left out of the study set.
+    pub fn enrichment(&self, study: &QueryResult) -> Vec<Enrichment> {
+        let population: HashSet<&Gene> = self.index().genes.iter().collect();
+        let population_total = self.index().genes.len();
+        let study_genes: HashSet<&Gene> = study.genes_iter()
+            .filter(|gene| population.contains(gene))
+            .collect();
+        let study_total = study_genes.len();
+        let log_factorials = LogFactorials::new(population_total);
+
+        let mut terms: Vec<&str> = self.terms().collect();
+        terms.sort_unstable();
+        let mut results: Vec<Enrichment> = terms.into_iter()
+            .filter_map(|term| {
+                let population_genes = self.genes(term, true);
+                if population_genes.is_empty() {
+                    return None;
+                }
+                let study_hits = population_genes.iter()
+                    .filter(|gene| study_genes.contains(**gene))
+                    .count();
+
+                let ontology_term = self.ontology().term(term);
+                Some(Enrichment {
+                    term: term.to_string(),
+                    name: ontology_term.map(|term| term.name.to_string()).unwrap_or_default(),
+                    aspect: ontology_term.and_then(|term| term.aspect()),
+                    study_genes: study_hits,
+                    study_total,
+                    population_genes: population_genes.len(),
+                    population_total,
+                    p_value: log_factorials.hypergeometric_sf(
+                        study_hits, study_total, population_genes.len(), population_total),
+                    bonferroni: 0.0,
+                    benjamini_hochberg: 0.0,
+                })
+            })
+            .collect();
+
+        let p_values: Vec<f64> = results.iter().map(|result| result.p_value).collect();
+        let corrected = bonferroni(&p_values).into_iter().zip(benjamini_hochberg(&p_values));
+        for (result, (bonferroni, benjamini_hochberg)) in results.iter_mut().zip(corrected) {
+            result.bonferroni = bonferroni;
+            result.benjamini_hochberg = benjamini_hochberg;
+        }
+
+        results.sort_by(|a, b| a.p_value.total_cmp(&b.p_value).then_with(|| a.term.cmp(&b.term)));
+        results
+    }
+}
+
+/// Writes enrichment results as a tab-separated table.
+pub fn write_enrichment_tsv<W: Write>(mut writer: W, results: &[Enrichment]) -> std::io::Result<()> {
+    writeln!(writer, "GO ID\tName\tAspect\tStudy\tStudy Total\tPopulation\tPopulation Total\tFold Enrichment\tP-value\tBonferroni\tBH FDR")?;
+    for result in results {
+        writeln!(writer, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.3}\t{:.3e}\t{:.3e}\t{:.3e}",
+                 result.term,
+                 result.name,
+                 result.aspect.map_or("", |aspect| aspect.as_str()),
+                 result.study_genes,
+                 result.study_total,
+                 result.population_genes,
+                 result.population_total,
+                 result.fold_enrichment(),
+                 result.p_value,
+                 result.bonferroni,
+                 result.benjamini_hochberg)?;
+    }
+    Ok(())
+}
+
+/// Multiplies each p-value by the number of p-values, up to 1.
+pub fn bonferroni(p_values: &[f64]) -> Vec<f64> {
+    let tests = p_values.len() as f64;
+    p_values.iter().map(|p| (p * tests).min(1.0)).collect()
+}
+
+/// The Benjamini-Hochberg adjusted p-value (or q-value) of each p-value,
+/// in the same order as the p-values.
+pub fn benjamini_hochberg(p_values: &[f64]) -> Vec<f64> {
+    let tests = p_values.len();
+    let mut order: Vec<usize> = (0..tests).collect();
+    order.sort_by(|&a, &b| p_values[a].total_cmp(&p_values[b]));
+
+    // Working down from the largest p-value, each adjusted p-value is the
+    // smallest of p * m / rank for its own rank and every larger one.
+    let mut adjusted = vec![0.0; tests];
+    let mut smallest: f64 = 1.0;
+    for (rank, &i) in order.iter().enumerate().rev() {
+        smallest = smallest.min(p_values[i] * tests as f64 / (rank + 1) as f64);
+        adjusted[i] = smallest;
+    }
+    adjusted
+}
+
+/// The natural logarithm of `n!` for every `n` up to some limit, for
+/// computing binomial coefficients of large numbers without overflow.
+struct LogFactorials(Vec<f64>);
+
+impl LogFactorials {
+    fn new(limit: usize) -> LogFactorials {
+        let mut log_factorials = Vec::with_capacity(limit + 1);
+        let mut sum = 0.0;
+        log_factorials.push(sum);
+        for n in 1..=limit {
+            sum += (n as f64).ln();
+            log_factorials.push(sum);
+        }
+        LogFactorials(log_factorials)
+    }
+
+    /// The natural logarithm of `n` choose `k`.
+    fn ln_choose(&self, n: usize, k: usize) -> f64 {
+        self.0[n] - self.0[k] - self.0[n - k]
+    }
+
+    /// The probability of drawing at least `hits` successes in `draws`
+    /// draws without replacement from a population of `total` items, of
+    /// which `successes` are successes.
+    fn hypergeometric_sf(&self, hits: usize, draws: usize, successes: usize, total: usize) -> f64 {
+        let ln_total = self.ln_choose(total, draws);
+        let lowest = hits.max((draws + successes).saturating_sub(total));
+        let p: f64 = (lowest..=draws.min(successes))
+            .map(|i| {
+                let ln_p = self.ln_choose(successes, i) + self.ln_choose(total - successes, draws - i) - ln_total;
+                ln_p.exp()
+            })
+            .sum();
+        p.min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::{Annotation, AnnotationRecord, EvidencePolicy, GeneRecord, Index, Ontology, Query, Segment, AnnotationStatus};
+
+    #[test]
+    fn test_hypergeometric() {
+        let log_factorials = LogFactorials::new(100);
+        // Both of the two study genes are among the two annotated genes
+        assert!((log_factorials.hypergeometric_sf(2, 2, 2, 4) - 1.0 / 6.0).abs() < 1e-12);
+        assert!((log_factorials.hypergeometric_sf(0, 2, 2, 4) - 1.0).abs() < 1e-12);
+        assert!((log_factorials.hypergeometric_sf(1, 2, 2, 4) - 5.0 / 6.0).abs() < 1e-12);
+        // A study set of 10 out of 100 genes which has all 5 annotated genes
+        let expected = 57_940_519.0 / 17_310_309_456_440.0;
+        assert!((log_factorials.hypergeometric_sf(5, 10, 5, 100) - expected).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_corrections() {
+        let p_values = [0.01, 0.04, 0.03, 0.2];
+        assert_eq!(bonferroni(&p_values), vec![0.04, 0.16, 0.12, 0.8]);
+        let adjusted: Vec<f64> = benjamini_hochberg(&p_values).iter()
+            .map(|q| (q * 1000.0).round() / 1000.0)
+            .collect();
+        assert_eq!(adjusted, vec![0.04, 0.053, 0.053, 0.2]);
+    }
+
+    #[test]
+    fn test_enrichment() {
+        let go_obo = "[Term]
+id: GO:0005575
+name: cellular_component
+namespace: cellular_component
+
+[Term]
+id: GO:0005634
+name: nucleus
+namespace: cellular_component
+is_a: GO:0005575 ! cellular_component
+";
+        let ontology = Ontology::parse_from(Cursor::new(go_obo)).unwrap();
+        let gene_records: Vec<GeneRecord> = ["AT1G01010", "AT1G01020", "AT1G01030", "AT1G01040"].iter()
+            .map(|gene_id| GeneRecord {
+                gene_id: gene_id.to_string(),
+                gene_product_type: "protein".to_string(),
+            })
+            .collect();
+        let genes: Vec<Gene> = gene_records.iter().map(Gene::from_record).collect();
+
+        let anno_records: Vec<AnnotationRecord> = [
+            ("AT1G01010", "GO:0005634", "IDA"),
+            ("AT1G01020", "GO:0005634", "IDA"),
+            ("AT1G01030", "GO:0005575", "IEA"),
+        ].iter()
+            .map(|(gene, go_term, evidence_code)| AnnotationRecord {
+                db: "TAIR".to_string(),
+                database_id: "locus:2031476".to_string(),
+                db_object_symbol: "".to_string(),
+                invert: "".to_string(),
+                go_term: go_term.to_string(),
+                reference: "TAIR:Communication:1345790".to_string(),
+                evidence_code: evidence_code.to_string(),
+                additional_evidence: "".to_string(),
+                aspect: Aspect::CellularComponent,
+                unique_gene_name: gene.to_string(),
+                alternative_gene_name: "".to_string(),
+                gene_product_type: "protein".to_string(),
+                taxon: "taxon:3702".to_string(),
+                date: "20190907".to_string(),
+                assigned_by: "TAIR".to_string(),
+                annotation_extension: "".to_string(),
+                gene_product_form_id: "".to_string(),
+            })
+            .collect();
+        let annotations: Vec<Annotation> = anno_records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .collect();
+        let index = Index::new(&genes, &annotations);
+        let terms = TermIndex::new(&index, &ontology);
+
+        let study = Query::Union(vec![Segment::new(Aspect::CellularComponent, AnnotationStatus::KnownExperimental).into()])
+            .execute(&index).unwrap();
+        let results = terms.enrichment(&study);
+        assert_eq!(results.len(), 2);
+
+        let nucleus = &results[0];
+        assert_eq!(nucleus.term, "GO:0005634");
+        assert_eq!(nucleus.name, "nucleus");
+        assert_eq!((nucleus.study_genes, nucleus.study_total), (2, 2));
+        assert_eq!((nucleus.population_genes, nucleus.population_total), (2, 4));
+        assert!((nucleus.p_value - 1.0 / 6.0).abs() < 1e-12);
+        assert!((nucleus.bonferroni - 1.0 / 3.0).abs() < 1e-12);
+        assert!((nucleus.fold_enrichment() - 2.0).abs() < 1e-12);
+
+        // The cellular component is propagated from the nucleus
+        let component = &results[1];
+        assert_eq!((component.study_genes, component.population_genes), (2, 3));
+        assert!((component.p_value - 0.5).abs() < 1e-12);
+
+        // Nothing is enriched in an empty study set
+        let empty = Query::Union(vec![Segment::new(Aspect::BiologicalProcess, AnnotationStatus::KnownExperimental).into()])
+            .execute(&index).unwrap();
+        let results = terms.enrichment(&empty);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| result.study_total == 0 && result.fold_enrichment() == 0.0));
+        assert!(results.iter().all(|result| (result.p_value - 1.0).abs() < 1e-12));
+
+        // Only the study genes in a smaller Index are counted
+        let smaller = Index::new(&genes[1..2], &annotations);
+        let results = TermIndex::new(&smaller, &ontology).enrichment(&study);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| (result.study_genes, result.study_total) == (1, 1)));
+        assert!(results.iter().all(|result| result.population_total == 1));
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+
+/// An error encountered while reading Gene or Annotation data.
+#[derive(Debug)]
+pub enum Error {
+    /// The underlying reader failed.
+    Io(std::io::Error),
+    /// A row of the input could not be parsed.
+    Parse(ParseError),
+    /// A configuration file (such as an `EvidencePolicy`) was invalid.
+    Config(String),
+}
+
+impl Error {
+    /// Shifts the line number of a parse error by the given number of lines.
+    ///
+    /// Parsers only know about the lines they are given, so when the
+    /// metadata and header of a file have already been consumed (e.g. by a
+    /// `MetadataReader`), this can be used to recover the line number in
+    /// the original file.
+    pub fn with_line_offset(self, offset: u64) -> Self {
+        match self {
+            Error::Parse(mut error) => {
+                error.line += offset;
+                Error::Parse(error)
+            }
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "failed to read input: {}", e),
+            Error::Parse(e) => write!(f, "{}", e),
+            Error::Config(e) => write!(f, "invalid configuration: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Parse(e) => Some(e),
+            Error::Config(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::Parse(error)
+    }
+}
+
+/// Describes a single row of input which could not be parsed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseError {
+    /// The 1-based line number of the row.
+    pub line: u64,
+    /// The name of the offending column, if the error belongs to one column.
+    pub column: Option<&'static str>,
+    /// The raw text of the row, with invalid UTF-8 replaced.
+    pub row: String,
+    /// The reason the row could not be parsed.
+    pub cause: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let Some(column) = self.column {
+            write!(f, ", column '{}'", column)?;
+        }
+        write!(f, ": {}: {:?}", self.cause, self.row)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    /// The row had fewer columns than the format requires.
+    ColumnCount { expected: usize, found: usize },
+    /// A field of the row was not valid UTF-8.
+    InvalidUtf8,
+    /// A field of the row could not be interpreted.
+    InvalidValue(String),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::ColumnCount { expected, found } => {
+                write!(f, "expected {} columns but found {}", expected, found)
+            }
+            ParseErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8"),
+            ParseErrorKind::InvalidValue(reason) => write!(f, "invalid value ({})", reason),
+        }
+    }
+}
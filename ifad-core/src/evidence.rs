@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::{AnnotationStatus, EcoMapping, Error};
+
+/// The GO evidence codes which are considered experimental by default.
+pub const EXPERIMENTAL_EVIDENCE: &[&str] = &[
+    "EXP", "IDA", "IPI", "IMP", "IGI", "IEP", "HTP", "HDA", "HMP", "HGI", "HEP",
+];
+
+/// The GO evidence codes which mean that nothing is known by default.
+pub const UNKNOWN_EVIDENCE: &[&str] = &["ND"];
+
+/// Rules for classifying annotations by their evidence codes.
+///
+/// Annotations with an experimental code are KnownExperimental, those with
+/// an unknown code are Unknown, and all others are KnownOther. Annotations
+/// with an excluded code (e.g. IEA) are kept, but are not used to classify
+/// Genes in an Index (see `IndexOptions::evidence`).
+///
+/// The lists may also contain ECO classes. Given an `EcoMapping` (ideally
+/// with the ECO ontology, see `EvidencePolicy::with_eco`), evidence matches
+/// an ECO class if its own class is that class or a descendant of it, e.g.
+/// `ECO:0000269` matches every kind of experimental evidence used in manual
+/// assertion. This also lets annotations whose evidence is an ECO class (as
+/// from GPAD files) match GO evidence codes.
+///
+/// A policy can be written as TOML or JSON, where any list that is left
+/// out keeps its default:
+///
+/// ```toml
+/// experimental = ["EXP", "IDA", "IPI", "IMP", "IGI", "IEP"]
+/// excluded = ["IEA"]
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvidencePolicy {
+    experimental: Vec<String>,
+    unknown: Vec<String>,
+    excluded: Vec<String>,
+    /// How to relate evidence codes and ECO classes, if at all.
+    #[serde(skip)]
+    pub(crate) eco: Option<EcoMapping>,
+    /// The evidence which falls under each list, given the `eco` mapping.
+    #[serde(skip)]
+    pub(crate) resolved: Option<ResolvedEvidence>,
+}
+
+/// The GO evidence codes and ECO classes which fall under each list of a
+/// policy, so that classifying an annotation is a lookup rather than a walk
+/// of the ECO ontology.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct ResolvedEvidence {
+    experimental: HashSet<String>,
+    unknown: HashSet<String>,
+    excluded: HashSet<String>,
+}
+
+impl EvidencePolicy {
+    /// Reads a policy from a TOML or JSON file, depending on its extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<EvidencePolicy, Error> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => EvidencePolicy::from_json(&contents),
+            _ => EvidencePolicy::from_toml(&contents),
+        }
+    }
+
+    pub fn from_toml(policy: &str) -> Result<EvidencePolicy, Error> {
+        toml::from_str(policy).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    pub fn from_json(policy: &str) -> Result<EvidencePolicy, Error> {
+        serde_json::from_str(policy).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    /// Relates evidence codes and ECO classes with the mapping, finding all
+    /// of the evidence which falls under each list up front.
+    pub fn with_eco(mut self, eco: EcoMapping) -> Self {
+        self.eco = Some(eco);
+        self.resolve();
+        self
+    }
+
+    pub fn experimental(&self) -> &[String] {
+        &self.experimental
+    }
+
+    pub fn unknown(&self) -> &[String] {
+        &self.unknown
+    }
+
+    pub fn excluded(&self) -> &[String] {
+        &self.excluded
+    }
+
+    pub fn set_experimental(&mut self, experimental: Vec<String>) {
+        self.experimental = experimental;
+        self.resolve();
+    }
+
+    pub fn set_unknown(&mut self, unknown: Vec<String>) {
+        self.unknown = unknown;
+        self.resolve();
+    }
+
+    pub fn set_excluded(&mut self, excluded: Vec<String>) {
+        self.excluded = excluded;
+        self.resolve();
+    }
+
+    /// Finds the evidence which falls under each list given the ECO
+    /// mapping, if there is one. This must be done whenever the lists or
+    /// the mapping change.
+    fn resolve(&mut self) {
+        let eco = match &self.eco {
+            Some(eco) => eco,
+            None => return,
+        };
+        let resolve = |classes: &[String]| -> HashSet<String> {
+            eco.evidence().into_iter()
+                .filter(|evidence| classes.iter().any(|class| eco.is_a(evidence, class)))
+                .chain(classes.iter().map(String::as_str))
+                .map(str::to_string)
+                .collect()
+        };
+        self.resolved = Some(ResolvedEvidence {
+            experimental: resolve(&self.experimental),
+            unknown: resolve(&self.unknown),
+            excluded: resolve(&self.excluded),
+        });
+    }
+
+    /// The status of an annotation with the given evidence code.
+    pub fn status_of(&self, evidence_code: &str) -> AnnotationStatus {
+        let resolved = self.resolved.as_ref();
+        if self.matches(&self.unknown, resolved.map(|r| &r.unknown), evidence_code) {
+            AnnotationStatus::Unknown
+        } else if self.matches(&self.experimental, resolved.map(|r| &r.experimental), evidence_code) {
+            AnnotationStatus::KnownExperimental
+        } else {
+            AnnotationStatus::KnownOther
+        }
+    }
+
+    /// Whether annotations with the given evidence code should be left out
+    /// of classification.
+    pub fn is_excluded(&self, evidence_code: &str) -> bool {
+        self.matches(&self.excluded, self.resolved.as_ref().map(|r| &r.excluded), evidence_code)
+    }
+
+    /// Whether evidence falls under any of the codes or classes in a list,
+    /// or in the evidence resolved from it.
+    fn matches(&self, classes: &[String], resolved: Option<&HashSet<String>>, evidence: &str) -> bool {
+        match (resolved, &self.eco) {
+            (Some(resolved), _) => resolved.contains(evidence),
+            (None, Some(eco)) => classes.iter().any(|class| eco.is_a(evidence, class)),
+            (None, None) => classes.iter().any(|class| class == evidence),
+        }
+    }
+}
+
+impl Default for EvidencePolicy {
+    fn default() -> Self {
+        EvidencePolicy {
+            experimental: EXPERIMENTAL_EVIDENCE.iter().map(|code| code.to_string()).collect(),
+            unknown: UNKNOWN_EVIDENCE.iter().map(|code| code.to_string()).collect(),
+            excluded: Vec::new(),
+            eco: None,
+            resolved: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::EcoOntology;
+
+    #[test]
+    fn test_default_policy() {
+        let policy = EvidencePolicy::default();
+        assert_eq!(policy.status_of("IDA"), AnnotationStatus::KnownExperimental);
+        assert_eq!(policy.status_of("IEA"), AnnotationStatus::KnownOther);
+        assert_eq!(policy.status_of("ND"), AnnotationStatus::Unknown);
+        assert!(!policy.is_excluded("IEA"));
+    }
+
+    #[test]
+    fn test_load_policy() {
+        let policy = EvidencePolicy::from_toml(r#"
+experimental = ["EXP", "IDA"]
+excluded = ["IEA"]
+"#).unwrap();
+        assert_eq!(policy.status_of("IDA"), AnnotationStatus::KnownExperimental);
+        assert_eq!(policy.status_of("IMP"), AnnotationStatus::KnownOther);
+        assert_eq!(policy.status_of("ND"), AnnotationStatus::Unknown);
+        assert!(policy.is_excluded("IEA"));
+
+        let json = EvidencePolicy::from_json(r#"{
+            "experimental": ["EXP", "IDA"],
+            "excluded": ["IEA"]
+        }"#).unwrap();
+        assert_eq!(json, policy);
+
+        let error = EvidencePolicy::from_toml("experimantal = [\"EXP\"]").unwrap_err();
+        assert!(matches!(error, Error::Config(_)));
+    }
+
+    #[test]
+    fn test_eco_policy() {
+        let eco_obo = "[Term]
+id: ECO:0000314
+is_a: ECO:0000269 ! experimental evidence used in manual assertion
+
+[Term]
+id: ECO:0007634
+is_a: ECO:0000314 ! direct assay evidence used in manual assertion
+";
+        let ontology = EcoOntology::parse_from(Cursor::new(eco_obo)).unwrap();
+        let mut policy = EvidencePolicy::default().with_eco(EcoMapping::default().with_ontology(ontology));
+        policy.set_experimental(vec!["ECO:0000269".to_string()]);
+        assert_eq!(policy.status_of("IDA"), AnnotationStatus::KnownExperimental);
+        assert_eq!(policy.status_of("ECO:0007634"), AnnotationStatus::KnownExperimental);
+        assert_eq!(policy.status_of("ECO:0000307"), AnnotationStatus::Unknown);
+        assert_eq!(policy.status_of("IEA"), AnnotationStatus::KnownOther);
+
+        // Resolving the lists up front agrees with walking the ontology
+        let unresolved = EvidencePolicy { resolved: None, ..policy.clone() };
+        for evidence in &["IDA", "IMP", "ND", "IEA", "ECO:0000269", "ECO:0000314", "ECO:0007634", "ECO:0000307", "ECO:9999999"] {
+            assert_eq!(policy.status_of(evidence), unresolved.status_of(evidence), "{}", evidence);
+        }
+    }
+}
@@ -1,5 +1,7 @@
+use std::collections::HashSet;
 use std::io::Write;
 use serde::Serialize;
+use crate::{Annotation, Compression, EcoMapping, GpadRecord, GpiRecord};
 
 pub struct GafExporter<I: Iterator> {
     metadata: String,
@@ -32,13 +34,60 @@ impl<T, I: Iterator<Item=T>> GafExporter<I>
         csv_writer.flush()?;
         Ok(())
     }
+
+    /// Writes all records like `write_all`, compressing the output.
+    pub fn write_compressed<W: Write>(
+        &mut self,
+        writer: W,
+        compression: Compression,
+    ) -> std::io::Result<()> {
+        compression.encode(writer, |writer| self.write_all(writer))
+    }
+}
+
+/// Writes annotations as a GPAD 2.0 file, along with a GPI 2.0 file
+/// describing the gene products they annotate.
+///
+/// Unlike `GafExporter`, which writes back the rows it was given, this
+/// converts annotations from any source (e.g. a `QueryResult`) into the
+/// GPAD and GPI columns.
+pub struct GpadExporter<'a> {
+    annotations: Vec<&'a Annotation<'a>>,
+    eco: EcoMapping,
+}
+
+impl<'a> GpadExporter<'a> {
+    pub fn new<I>(annotations: I, eco: EcoMapping) -> GpadExporter<'a>
+        where I: IntoIterator<Item=&'a Annotation<'a>>
+    {
+        GpadExporter { annotations: annotations.into_iter().collect(), eco }
+    }
+
+    /// Writes one GPAD row for each annotation.
+    pub fn write_gpad<W: Write>(&self, writer: W, compression: Compression) -> std::io::Result<()> {
+        let records = self.annotations.iter()
+            .map(|annotation| GpadRecord::from_annotation(annotation, &self.eco));
+        GafExporter::new("!gpad-version: 2.0\n".to_string(), String::new(), records)
+            .write_compressed(writer, compression)
+    }
+
+    /// Writes one GPI row for each gene product which is annotated, in the
+    /// order they are first annotated.
+    pub fn write_gpi<W: Write>(&self, writer: W, compression: Compression) -> std::io::Result<()> {
+        let mut seen = HashSet::new();
+        let records = self.annotations.iter()
+            .map(|annotation| GpiRecord::from_annotation(annotation))
+            .filter(|record| seen.insert(record.db_object_id.clone()));
+        GafExporter::new("!gpi-version: 2.0\n".to_string(), String::new(), records)
+            .write_compressed(writer, compression)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::io::Cursor;
-    use crate::{AnnotationRecord, MetadataReader, GeneRecord};
+    use crate::{AnnotationRecord, MetadataReader, GeneRecord, Aspect, EvidencePolicy};
 
     #[test]
     fn test_export_annotations() {
@@ -98,4 +147,91 @@ AT1G01046	miRNA_primary_transcript
         let output_string = String::from_utf8(output).unwrap();
         assert_eq!(&genes_file, &output_string);
     }
+
+    #[test]
+    fn test_export_streamed_annotations() {
+        let annotations_file = r"!gaf-version: 2.1
+DB	DB Object ID	DB Object Symbol	Qualifier	GO ID	DB:Reference (JDB:Reference)	Evidence Code	With (or) From	Aspect	DB Object Name	DB Object Type	Taxon	Date	Assigned By	Annotation Extension	Gene Product Form ID
+TAIR	locus:2031476	ENO1		GO:0000015	TAIR:AnalysisReference:501756966	IEA	InterPro:IPR000941	C	AT1G74030	AT1G74030|ENO1|enolase 1|F2P9.10|F2P9_10	protein	taxon:3702	20190907	InterPro		TAIR:locus:2031476
+TAIR	locus:2017963	TUN		GO:0000030	TAIR:AnalysisReference:501756966	IEA	InterPro:IPR026051	F	AT1G16570	AT1G16570|TUN|TURAN|F19K19.11|F19K19_11	protein	taxon:3702	20190907	InterPro		TAIR:locus:2017963
+TAIR	locus:2032970	AT1G25260		GO:0000027	TAIR:AnalysisReference:501756966	IEA	InterPro:IPR033867	P	AT1G25260	AT1G25260|F4F7.35|F4F7_35	protein	taxon:3702	20190404	InterPro		TAIR:locus:2032970
+TAIR	locus:2064821	CTEXP		GO:0000049	TAIR:Publication:501746929	IDA		F	AT2G40730	AT2G40730|CTEXP|cytoplasmic tRNA export protein|T7D17.9|T7D17_9	protein	taxon:3702	20120106	TAIR		TAIR:locus:2064821
+";
+        let mut reader = MetadataReader::new(Cursor::new(&annotations_file));
+        reader.read_metadata().expect("should read metadata");
+        let metadata = reader.metadata().expect("should get metadata").to_string();
+        let header = reader.header().expect("should get header").to_string();
+
+        // Filter the annotations as they are parsed, without collecting them
+        let records = AnnotationRecord::iter_from(&mut reader)
+            .map(|record| record.expect("should parse annotation"))
+            .filter(|record| record.aspect == Aspect::MolecularFunction);
+        let mut exporter = GafExporter::new(metadata, header, records);
+
+        let mut output = Vec::new();
+        exporter.write_all(Cursor::new(&mut output)).unwrap();
+        let output_string = String::from_utf8(output).unwrap();
+        let expected: String = annotations_file.lines()
+            .filter(|line| !line.starts_with("TAIR") || line.contains("\tF\t"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert_eq!(expected, output_string);
+    }
+
+    #[test]
+    fn test_export_gpad_and_gpi() {
+        let annotations_file = r"!gaf-version: 2.2
+TAIR	locus:2031476	ENO1	located_in	GO:0000015	TAIR:AnalysisReference:501756966	IEA	InterPro:IPR000941	C	AT1G74030	AT1G74030|ENO1	protein	taxon:3702	20190907	InterPro		TAIR:locus:2031476
+TAIR	locus:2031476	ENO1	NOT|is_active_in	GO:0000016	PMID:12345	IDA		C	AT1G74030	AT1G74030|ENO1	protein	taxon:3702|taxon:562	20190907	TAIR		
+TAIR	locus:2017963	TUN	enables	GO:0000030	TAIR:AnalysisReference:501756966	IEA	InterPro:IPR026051	F	AT1G16570	AT1G16570|TUN	protein	taxon:3702	20190907	InterPro		TAIR:locus:2017963
+TAIR	locus:2064821	CTEXP		GO:0000049	TAIR:Publication:501746929	IDA		C	AT2G40730	AT2G40730|CTEXP	ncRNA	taxon:3702	20120106	TAIR		TAIR:locus:2064821
+";
+        let reader = MetadataReader::new(Cursor::new(&annotations_file)).without_header();
+        let records = AnnotationRecord::parse_from(reader).expect("should parse annotations");
+        let annotations: Vec<_> = records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .filter(|annotation| annotation.aspect == Aspect::CellularComponent)
+            .collect();
+
+        let exporter = GpadExporter::new(&annotations, EcoMapping::default());
+        let mut gpad = Vec::new();
+        exporter.write_gpad(&mut gpad, Compression::None).unwrap();
+        let mut gpi = Vec::new();
+        exporter.write_gpi(&mut gpi, Compression::None).unwrap();
+
+        assert_eq!(String::from_utf8(gpad).unwrap(), r"!gpad-version: 2.0
+TAIR:locus:2031476		RO:0001025	GO:0000015	TAIR:AnalysisReference:501756966	ECO:0000501	InterPro:IPR000941		2019-09-07	InterPro		
+TAIR:locus:2031476	NOT	RO:0002432	GO:0000016	PMID:12345	ECO:0000314		NCBITaxon:562	2019-09-07	TAIR		
+TAIR:locus:2064821		RO:0001025	GO:0000049	TAIR:Publication:501746929	ECO:0000314			2012-01-06	TAIR		
+");
+        assert_eq!(String::from_utf8(gpi).unwrap(), r"!gpi-version: 2.0
+TAIR:locus:2031476	ENO1	AT1G74030	AT1G74030|ENO1	PR:000000001	NCBITaxon:3702					
+TAIR:locus:2064821	CTEXP	AT2G40730	AT2G40730|CTEXP	SO:0000655	NCBITaxon:3702					
+");
+    }
+
+    #[test]
+    fn test_gpi_round_trip() {
+        let gpad = "!gpad-version: 2.0
+TAIR:locus:2031476		RO:0001025	GO:0000015	TAIR:AnalysisReference:501756966	ECO:0000501	InterPro:IPR000941		2019-09-07	InterPro		
+TAIR:locus:2017963	NOT	RO:0002327	GO:0000030	PMID:12345	ECO:0000314			2019-09-07	TAIR		
+";
+        let gpi = "!gpi-version: 2.0
+TAIR:locus:2031476	ENO1	enolase 1	AT1G74030|F2P9.10	PR:000000001	NCBITaxon:3702					
+TAIR:locus:2017963	TUN	tunicamycin induced 1	AT1G16570	SO:0000655	NCBITaxon:3702					
+";
+        let gpad_records = GpadRecord::parse_from(MetadataReader::new(Cursor::new(gpad)).without_header()).unwrap();
+        let gpi_records = GpiRecord::parse_from(MetadataReader::new(Cursor::new(gpi)).without_header()).unwrap();
+        let eco = EcoMapping::default();
+        let records: Vec<AnnotationRecord> = gpad_records.iter().zip(&gpi_records)
+            .map(|(gpad, gpi)| AnnotationRecord::from_gpad(gpad, Some(gpi), &eco).unwrap())
+            .collect();
+        let annotations: Vec<_> = records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .collect();
+
+        let mut output = Vec::new();
+        GpadExporter::new(&annotations, eco).write_gpi(&mut output, Compression::None).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), gpi);
+    }
 }
@@ -1,8 +1,64 @@
 use std::collections::{HashMap, HashSet};
-use crate::{Aspect, AnnotationStatus, Gene, Annotation};
+use crate::{AliasIndex, Aspect, AnnotationStatus, Gene, Annotation, Diagnostic, Diagnostics, EvidencePolicy, Ontology};
+use crate::validation;
 
 pub type GeneIndex<'a> = HashMap<Aspect, HashMap<AnnotationStatus, HashSet<&'a Gene<'a>>>>;
 pub type AnnoIndex<'a, 'b> = HashMap<String, (&'a Gene<'a>, HashSet<&'b Annotation<'b>>)>;
+/// The number of Genes in each segment.
+pub type SegmentCounts = HashMap<Aspect, HashMap<AnnotationStatus, usize>>;
+
+/// How `NOT` annotations are used when classifying Genes.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum NegationPolicy {
+    /// Negated annotations do not classify genes at all. A gene with only
+    /// negated annotations to an Aspect is Unannotated for that Aspect.
+    #[default]
+    Ignore,
+    /// Negated annotations are classified as `AnnotationStatus::Negated`.
+    /// A gene is Negated for an Aspect when all of its annotations to that
+    /// Aspect are negated.
+    Separate,
+    /// Negated annotations classify genes like any other annotation.
+    Include,
+}
+
+impl NegationPolicy {
+    /// The status an annotation has under this policy, or None if the
+    /// annotation should not be used for classification.
+    pub fn status_of(&self, annotation: &Annotation) -> Option<AnnotationStatus> {
+        if !annotation.invert {
+            return Some(annotation.annotation_status);
+        }
+
+        match self {
+            NegationPolicy::Ignore => None,
+            NegationPolicy::Separate => Some(AnnotationStatus::Negated),
+            NegationPolicy::Include => Some(annotation.annotation_status),
+        }
+    }
+}
+
+/// Options which control how an Index classifies Genes.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct IndexOptions {
+    pub negation: NegationPolicy,
+    /// Annotations with one of this policy's excluded evidence codes are
+    /// not used for classification. Annotations are given their status by
+    /// the policy passed to `Annotation::from_record`, which should
+    /// normally be the same one.
+    pub evidence: EvidencePolicy,
+}
+
+impl IndexOptions {
+    /// The status an annotation has under these options, or None if the
+    /// annotation should not be used for classification.
+    pub fn status_of(&self, annotation: &Annotation) -> Option<AnnotationStatus> {
+        if self.evidence.is_excluded(annotation.evidence_code) {
+            return None;
+        }
+        self.negation.status_of(annotation)
+    }
+}
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct Index<'a, 'b> {
@@ -10,6 +66,11 @@ pub struct Index<'a, 'b> {
     pub annotations: &'b [Annotation<'b>],
     pub gene_index: GeneIndex<'a>,
     pub anno_index: AnnoIndex<'a, 'b>,
+    /// The names of the Genes, which annotations were matched to them by.
+    pub aliases: AliasIndex<'a>,
+    /// Annotations which could not be matched to any of the Genes.
+    pub unmatched_annotations: Vec<&'b Annotation<'b>>,
+    pub options: IndexOptions,
 }
 
 impl Index<'_, '_> {
@@ -27,7 +88,28 @@ impl Index<'_, '_> {
         genes: &'a [Gene],
         annotations: &'b [Annotation]
     ) -> Index<'a, 'b> {
-        let mut gene_index: GeneIndex = HashMap::new();
+        Index::with_options(genes, annotations, IndexOptions::default())
+    }
+
+    /// Create a new Index like `Index::new`, choosing how Genes are classified.
+    pub fn with_options<'a, 'b>(
+        genes: &'a [Gene],
+        annotations: &'b [Annotation],
+        options: IndexOptions,
+    ) -> Index<'a, 'b> {
+        Index::with_aliases(genes, annotations, options, AliasIndex::new(genes))
+    }
+
+    /// Create a new Index like `Index::with_options`, matching annotations to
+    /// Genes by the given aliases (such as from an alias file) as well as
+    /// those the annotations give themselves.
+    pub fn with_aliases<'a, 'b>(
+        genes: &'a [Gene],
+        annotations: &'b [Annotation],
+        options: IndexOptions,
+        mut aliases: AliasIndex<'a>,
+    ) -> Index<'a, 'b> {
+        aliases.insert_annotations(annotations);
         let mut anno_index: AnnoIndex = HashMap::new();
 
         // The annotation index should have a key for each Gene that exists.
@@ -37,76 +119,88 @@ impl Index<'_, '_> {
             anno_index.insert(gene.gene_id.to_string(), (&gene, HashSet::new()));
         }
 
-        // First pass: Put all KnownExperimental and Unknown annotations
-        // directly into the index, but put all KnownOther annotations into
-        // a temporary index.
-        //
-        // We will come back for a second pass to determine whether each of the
-        // KnownOther annotations should be placed in the permanent index.
-        let mut known_other_index: GeneIndex = HashMap::new();
+        // Match each annotation to the Gene it annotates
+        let mut matched_annotations = Vec::new();
+        let mut unmatched_annotations = Vec::new();
         for annotation in annotations {
-            let gene_id = annotation.gene_in(&anno_index)
-                .map(|gene| gene.gene_id.to_string());
-            let gene_id = match gene_id {
+            let gene_id = match aliases.gene_id_of(annotation) {
                 Some(gene_id) => gene_id,
-                None => continue, // TODO collect warnings
+                None => {
+                    unmatched_annotations.push(annotation);
+                    continue;
+                }
             };
             let (gene, gene_annotations) = anno_index
-                .get_mut(&*gene_id).expect("should get gene");
+                .get_mut(gene_id).expect("should get gene");
             gene_annotations.insert(annotation);
-
-            // Insert into temporary index for KnownOther, or
-            // permanent index for KnownExperimental and Unknown.
-            let index_to_insert =
-                if annotation.annotation_status == AnnotationStatus::KnownOther {
-                    &mut known_other_index
-                } else {
-                    &mut gene_index
-                };
-
-            index_to_insert
-                .entry(annotation.aspect)
-                .or_insert_with(HashMap::new)
-                .entry(annotation.annotation_status)
-                .or_insert_with(HashSet::new)
-                .insert(&*gene);
+            matched_annotations.push((*gene, annotation));
         }
 
-        // Create an iterator over all Genes in the temporary KnownOther
-        // index where each Gene is paired with the Aspect it was annotated with
-        let known_other_flat = known_other_index.into_iter()
-            .flat_map(|(aspect, by_status)| {
-                by_status.into_iter().flat_map(move |(_, genes)| {
-                    genes.into_iter().map(move |gene| (aspect, gene))
-                })
-            });
+        let gene_index = classify(matched_annotations, &options);
 
-        // Second Pass: Adding KnownOther genes to the permanent index.
-        //
-        // For each Gene (G) with Aspect (A) in the KnownOther index:
-        //   * Look up all KnownExperimental genes in aspect A in the permanent index
-        //   * Determine whether gene G appears in that KnownExperimental set
-        //   * If gene G does not appear in the KnownExperimental set, add G to
-        //     the KnownOther set for aspect A in the permanent index.
-        for (aspect, gene) in known_other_flat {
-            let exp_for_aspect = gene_index.get(&aspect).and_then(|by_status| {
-                by_status.get(&AnnotationStatus::KnownExperimental)
-            });
+        Index { genes, annotations, gene_index, anno_index, aliases, unmatched_annotations, options }
+            .index_unannotated()
+    }
 
-            let exp_contains = exp_for_aspect
-                .map(|exp| exp.contains(gene))
-                .unwrap_or(false);
+    /// The Gene which an annotation was matched to in this Index, by its
+    /// ID or by an alias (see `AliasIndex::gene_id_of`).
+    pub fn gene_of(&self, annotation: &Annotation) -> Option<&Gene<'_>> {
+        let gene_id = self.aliases.gene_id_of(annotation)?;
+        self.anno_index.get(gene_id).map(|&(gene, _)| gene)
+    }
+
+    /// The status that an annotation contributes to its Gene in this Index,
+    /// or None if the annotation is not used for classification.
+    pub fn status_of(&self, annotation: &Annotation) -> Option<AnnotationStatus> {
+        self.options.status_of(annotation)
+    }
 
-            if !exp_contains {
-                gene_index.entry(aspect)
-                    .or_insert_with(HashMap::new)
-                    .entry(AnnotationStatus::KnownOther)
-                    .or_insert_with(HashSet::new)
-                    .insert(gene);
+    /// Reports the annotations which were left out of this Index because
+    /// their genes could not be found, and the aliases which are shared by
+    /// more than one Gene.
+    pub fn diagnostics(&self) -> Diagnostics {
+        let mut diagnostics = Diagnostics::new();
+        for annotation in &self.unmatched_annotations {
+            diagnostics.push(Diagnostic::unknown_gene(annotation));
+        }
+        for (alias, gene_ids) in self.aliases.ambiguous() {
+            diagnostics.push(Diagnostic::AmbiguousAlias {
+                alias: alias.to_string(),
+                gene_ids: gene_ids.iter().map(|gene_id| gene_id.to_string()).collect(),
+            });
+        }
+        diagnostics
+    }
+
+    /// Reports the annotations in this Index whose Aspect does not match
+    /// the namespace of their GO term, or whose GO term is obsolete.
+    ///
+    /// To correct or drop such annotations before they are classified, see
+    /// `ValidationPolicy`.
+    pub fn validate(&self, ontology: &Ontology) -> Diagnostics {
+        let mut diagnostics = Diagnostics::new();
+        for annotation in self.annotations {
+            for problem in validation::validate(annotation.record, ontology) {
+                diagnostics.push(problem);
             }
         }
+        diagnostics
+    }
 
-        Index { genes, annotations, gene_index, anno_index }.index_unannotated()
+    /// Counts the Genes of each gene product type (e.g. `protein_coding`)
+    /// in each segment.
+    pub fn product_type_counts(&self) -> HashMap<&str, SegmentCounts> {
+        let mut counts: HashMap<&str, SegmentCounts> = HashMap::new();
+        for (&aspect, by_status) in &self.gene_index {
+            for (&status, genes) in by_status {
+                for gene in genes {
+                    *counts.entry(gene.gene_product_type).or_default()
+                        .entry(aspect).or_default()
+                        .entry(status).or_default() += 1;
+                }
+            }
+        }
+        counts
     }
 
     /// Calculates the Unannotated section for each Aspect in the index.
@@ -117,12 +211,6 @@ impl Index<'_, '_> {
     /// aspect.
     fn index_unannotated(mut self) -> Self {
 
-        let aspects: &[Aspect] = &[
-            Aspect::CellularComponent,
-            Aspect::MolecularFunction,
-            Aspect::BiologicalProcess,
-        ];
-
         // For each Aspect, collect a set of all Genes which are annotated to it
         let genes_by_aspect: HashMap<Aspect, HashSet<&Gene>> = self.gene_index.iter()
             .map(|(&aspect, by_status)| {
@@ -141,7 +229,7 @@ impl Index<'_, '_> {
         // If gene G does not appear in the annotations for aspect A, then
         // add gene G to the "Unannotated" set for aspect A.
         for &gene in genes_iter {
-            for aspect in aspects.iter() {
+            for aspect in Aspect::ALL.iter() {
                 let in_aspect = genes_by_aspect.get(aspect)
                     .map(|genes| genes.contains(gene))
                     .unwrap_or(false);
@@ -159,6 +247,110 @@ impl Index<'_, '_> {
     }
 }
 
+/// Classifies Genes by the Aspect and AnnotationStatus of their annotations,
+/// given each annotation paired with the Gene it annotates.
+///
+/// A Gene is KnownOther for an Aspect only if it is not KnownExperimental
+/// for it, and Negated only if it has no other status for it. Genes without
+/// any annotations to an Aspect are not made Unannotated here.
+pub(crate) fn classify<'a, 'b, I>(annotations: I, options: &IndexOptions) -> GeneIndex<'a>
+    where I: IntoIterator<Item=(&'a Gene<'a>, &'b Annotation<'b>)>
+{
+    let mut gene_index: GeneIndex = HashMap::new();
+
+    // First pass: Put all KnownExperimental and Unknown annotations
+    // directly into the index, but put all KnownOther and Negated
+    // annotations into temporary indexes.
+    //
+    // We will come back for a second pass to determine whether each of the
+    // KnownOther annotations should be placed in the permanent index.
+    let mut known_other_index: GeneIndex = HashMap::new();
+    let mut negated_index: GeneIndex = HashMap::new();
+    for (gene, annotation) in annotations {
+        // Negated or excluded annotations may not be used for classification
+        let status = match options.status_of(annotation) {
+            Some(status) => status,
+            None => continue,
+        };
+
+        // Insert into temporary index for KnownOther and Negated, or
+        // permanent index for KnownExperimental and Unknown.
+        let index_to_insert = match status {
+            AnnotationStatus::KnownOther => &mut known_other_index,
+            AnnotationStatus::Negated => &mut negated_index,
+            _ => &mut gene_index,
+        };
+
+        index_to_insert
+            .entry(annotation.aspect)
+            .or_insert_with(HashMap::new)
+            .entry(status)
+            .or_insert_with(HashSet::new)
+            .insert(gene);
+    }
+
+    // Create an iterator over all Genes in the temporary KnownOther
+    // index where each Gene is paired with the Aspect it was annotated with
+    let known_other_flat = known_other_index.into_iter()
+        .flat_map(|(aspect, by_status)| {
+            by_status.into_values().flat_map(move |genes| {
+                genes.into_iter().map(move |gene| (aspect, gene))
+            })
+        });
+
+    // Second Pass: Adding KnownOther genes to the permanent index.
+    //
+    // For each Gene (G) with Aspect (A) in the KnownOther index:
+    //   * Look up all KnownExperimental genes in aspect A in the permanent index
+    //   * Determine whether gene G appears in that KnownExperimental set
+    //   * If gene G does not appear in the KnownExperimental set, add G to
+    //     the KnownOther set for aspect A in the permanent index.
+    for (aspect, gene) in known_other_flat {
+        let exp_for_aspect = gene_index.get(&aspect).and_then(|by_status| {
+            by_status.get(&AnnotationStatus::KnownExperimental)
+        });
+
+        let exp_contains = exp_for_aspect
+            .map(|exp| exp.contains(gene))
+            .unwrap_or(false);
+
+        if !exp_contains {
+            gene_index.entry(aspect)
+                .or_insert_with(HashMap::new)
+                .entry(AnnotationStatus::KnownOther)
+                .or_insert_with(HashSet::new)
+                .insert(gene);
+        }
+    }
+
+    // Third Pass: Adding Negated genes to the permanent index.
+    //
+    // A gene (G) is only Negated for an Aspect (A) if it has no other
+    // annotations which placed it in the permanent index for aspect A.
+    let negated_flat = negated_index.into_iter()
+        .flat_map(|(aspect, by_status)| {
+            by_status.into_values().flat_map(move |genes| {
+                genes.into_iter().map(move |gene| (aspect, gene))
+            })
+        });
+
+    for (aspect, gene) in negated_flat {
+        let annotated = gene_index.get(&aspect)
+            .map(|by_status| by_status.values().any(|genes| genes.contains(gene)))
+            .unwrap_or(false);
+
+        if !annotated {
+            gene_index.entry(aspect)
+                .or_insert_with(HashMap::new)
+                .entry(AnnotationStatus::Negated)
+                .or_insert_with(HashSet::new)
+                .insert(gene);
+        }
+    }
+
+    gene_index
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -180,7 +372,6 @@ mod tests {
             .map(|record| Gene::from_record(record))
             .collect();
 
-        let experimental_evidence = &["EXP", "IDA", "IPI", "IMP", "IGI", "IEP", "HTP", "HDA", "HMP", "HGI", "HEP"];
         let annotation_records: Vec<AnnotationRecord> = vec![
             AnnotationRecord {
                 db: "TAIR".to_string(),
@@ -241,7 +432,7 @@ mod tests {
             },
         ];
         let annotations: Vec<_> = annotation_records.iter()
-            .map(|record| Annotation::from_record(record, &experimental_evidence[..]))
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
             .collect();
 
         let index = Index::new(&genes, &annotations);
@@ -281,12 +472,168 @@ mod tests {
         anno_index.entry(genes[1].gene_id.to_string())
             .or_insert((&genes[1], gene1_annotations));
 
+        let mut aliases = AliasIndex::new(&genes);
+        aliases.insert_annotations(&annotations);
+
         let expected_index = Index {
             genes: &genes,
             annotations: &annotations,
             gene_index,
             anno_index,
+            aliases,
+            unmatched_annotations: vec![],
+            options: IndexOptions::default(),
         };
         assert_eq!(expected_index, index);
     }
+
+    #[test]
+    fn test_unmatched_annotations() {
+        let gene_records = vec![
+            GeneRecord {
+                gene_id: "AT1G74030".to_string(),
+                gene_product_type: "protein".to_string(),
+            },
+        ];
+        let genes: Vec<Gene> = gene_records.iter()
+            .map(|record| Gene::from_record(record))
+            .collect();
+
+        let annotation_records = vec![
+            AnnotationRecord {
+                db: "TAIR".to_string(),
+                database_id: "locus:2043067".to_string(),
+                db_object_symbol: "ENOC".to_string(),
+                invert: "".to_string(),
+                go_term: "GO:0000015".to_string(),
+                reference: "TAIR:AnalysisReference:501756966".to_string(),
+                evidence_code: "IEA".to_string(),
+                additional_evidence: "InterPro:IPR000941".to_string(),
+                aspect: Aspect::CellularComponent,
+                unique_gene_name: "AT2G29560".to_string(),
+                alternative_gene_name: "AT2G29560|ENOC".to_string(),
+                gene_product_type: "protein".to_string(),
+                taxon: "taxon:3702".to_string(),
+                date: "20190408".to_string(),
+                assigned_by: "InterPro".to_string(),
+                annotation_extension: "".to_string(),
+                gene_product_form_id: "TAIR:locus:2043067".to_string(),
+            },
+        ];
+        let annotations: Vec<_> = annotation_records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .collect();
+
+        let index = Index::new(&genes, &annotations);
+        assert_eq!(index.unmatched_annotations, vec![&annotations[0]]);
+
+        let diagnostics: Vec<_> = index.diagnostics().iter().cloned().collect();
+        assert_eq!(diagnostics, vec![Diagnostic::UnknownGene {
+            gene_name: "AT2G29560".to_string(),
+            go_term: "GO:0000015".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn test_negation_policies() {
+        let gene_records = vec![
+            GeneRecord {
+                gene_id: "AT4G34200".to_string(),
+                gene_product_type: "protein".to_string(),
+            },
+        ];
+        let genes: Vec<Gene> = gene_records.iter()
+            .map(|record| Gene::from_record(record))
+            .collect();
+
+        let annotation_records = vec![
+            AnnotationRecord {
+                db: "TAIR".to_string(),
+                database_id: "locus:2124266".to_string(),
+                db_object_symbol: "EDA9".to_string(),
+                invert: "NOT".to_string(),
+                go_term: "GO:0005829".to_string(),
+                reference: "PMID:21166475".to_string(),
+                evidence_code: "IDA".to_string(),
+                additional_evidence: "".to_string(),
+                aspect: Aspect::CellularComponent,
+                unique_gene_name: "AT4G34200".to_string(),
+                alternative_gene_name: "AT4G34200|PGDH1".to_string(),
+                gene_product_type: "protein".to_string(),
+                taxon: "taxon:3702".to_string(),
+                date: "20180831".to_string(),
+                assigned_by: "TAIR".to_string(),
+                annotation_extension: "".to_string(),
+                gene_product_form_id: "".to_string(),
+            },
+        ];
+        let annotations: Vec<_> = annotation_records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .collect();
+
+        // Find each status the gene has for CellularComponent under a policy
+        let cc_statuses = |negation: NegationPolicy| {
+            let options = IndexOptions { negation, ..IndexOptions::default() };
+            let index = Index::with_options(&genes, &annotations, options);
+            let statuses: Vec<_> = index.gene_index[&Aspect::CellularComponent].iter()
+                .filter(|(_, status_genes)| status_genes.contains(&genes[0]))
+                .map(|(status, _)| *status)
+                .collect();
+            statuses
+        };
+
+        assert_eq!(cc_statuses(NegationPolicy::Ignore), vec![AnnotationStatus::Unannotated]);
+        assert_eq!(cc_statuses(NegationPolicy::Separate), vec![AnnotationStatus::Negated]);
+        assert_eq!(cc_statuses(NegationPolicy::Include), vec![AnnotationStatus::KnownExperimental]);
+    }
+
+    #[test]
+    fn test_excluded_evidence() {
+        let gene_records = vec![
+            GeneRecord {
+                gene_id: "AT1G74030".to_string(),
+                gene_product_type: "protein".to_string(),
+            },
+        ];
+        let genes: Vec<Gene> = gene_records.iter()
+            .map(|record| Gene::from_record(record))
+            .collect();
+
+        let annotation_record = |evidence_code: &str| AnnotationRecord {
+            db: "TAIR".to_string(),
+            database_id: "locus:2031476".to_string(),
+            db_object_symbol: "ENO1".to_string(),
+            invert: "".to_string(),
+            go_term: "GO:0000015".to_string(),
+            reference: "TAIR:AnalysisReference:501756966".to_string(),
+            evidence_code: evidence_code.to_string(),
+            additional_evidence: "".to_string(),
+            aspect: Aspect::CellularComponent,
+            unique_gene_name: "AT1G74030".to_string(),
+            alternative_gene_name: "AT1G74030|ENO1".to_string(),
+            gene_product_type: "protein".to_string(),
+            taxon: "taxon:3702".to_string(),
+            date: "20190907".to_string(),
+            assigned_by: "InterPro".to_string(),
+            annotation_extension: "".to_string(),
+            gene_product_form_id: "".to_string(),
+        };
+        let annotation_records = vec![annotation_record("IEA"), annotation_record("ND")];
+
+        let mut evidence = EvidencePolicy::default();
+        evidence.set_excluded(vec!["IEA".to_string()]);
+        let annotations: Vec<_> = annotation_records.iter()
+            .map(|record| Annotation::from_record(record, &evidence))
+            .collect();
+        let options = IndexOptions { evidence, ..IndexOptions::default() };
+        let index = Index::with_options(&genes, &annotations, options);
+
+        let statuses: Vec<_> = index.gene_index[&Aspect::CellularComponent].iter()
+            .filter(|(_, status_genes)| status_genes.contains(&genes[0]))
+            .map(|(status, _)| *status)
+            .collect();
+        assert_eq!(statuses, vec![AnnotationStatus::Unknown]);
+        assert_eq!(index.status_of(&annotations[0]), None);
+        assert_eq!(index.status_of(&annotations[1]), Some(AnnotationStatus::Unknown));
+    }
 }
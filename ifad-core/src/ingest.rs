@@ -1,12 +1,69 @@
-use std::io::{Read, BufRead, Cursor, Error};
+use std::io::{Read, BufRead, Cursor};
+use std::marker::PhantomData;
 use serde::{Deserialize, Serialize};
-use crate::Aspect;
+use serde::de::{DeserializeOwned, DeserializeSeed, IntoDeserializer, SeqAccess};
+use serde::de::value::SeqAccessDeserializer;
+use std::convert::TryFrom;
+use crate::{Aspect, Annotation, Qualifier, Relation};
+use crate::eco::EcoMapping;
+use crate::compression::Compression;
+use crate::error::{Error, ParseError, ParseErrorKind};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+
+/// A version of the GAF format, as declared by a file's `!gaf-version` line.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum GafVersion {
+    V1_0,
+    V2_0,
+    V2_1,
+    V2_2,
+}
+
+impl GafVersion {
+    /// Finds the `!gaf-version` declaration in a file's metadata.
+    pub fn from_metadata(metadata: &str) -> Option<GafVersion> {
+        metadata.lines()
+            .filter_map(|line| line.trim().strip_prefix("!gaf-version:"))
+            .map(str::trim)
+            .find_map(|version| GafVersion::try_from(version).ok())
+    }
+}
+
+impl TryFrom<&str> for GafVersion {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let version = match value {
+            "1.0" => GafVersion::V1_0,
+            "2.0" => GafVersion::V2_0,
+            "2.1" => GafVersion::V2_1,
+            "2.2" => GafVersion::V2_2,
+            _ => return Err(()),
+        };
+        Ok(version)
+    }
+}
+
+/// A kind of row which can be parsed from a tab-separated file.
+pub trait Record: DeserializeOwned {
+    /// The names of the columns of this kind of row, in order.
+    const COLUMNS: &'static [&'static str];
+
+    /// Checks rules about a row that deserialization can't, such as rules
+    /// spanning several columns. On failure, returns the index of the
+    /// offending column along with the reason.
+    fn validate(&self, _gaf_version: Option<GafVersion>) -> Result<(), (usize, ParseErrorKind)> {
+        Ok(())
+    }
+}
 
 pub struct MetadataReader<B> {
     reader: B,
     metadata: String,
     header: String,
     metadata_finished: bool,
+    metadata_lines: u64,
+    has_header: bool,
     buffer: Cursor<String>,
 }
 
@@ -17,10 +74,19 @@ impl<B: BufRead> MetadataReader<B> {
             metadata: String::new(),
             header: String::new(),
             metadata_finished: false,
+            metadata_lines: 0,
+            has_header: true,
             buffer: Cursor::new(String::new()),
         }
     }
 
+    /// Treats the first line after the metadata as data rather than as a
+    /// header, as in GPAD and GPI files.
+    pub fn without_header(mut self) -> Self {
+        self.has_header = false;
+        self
+    }
+
     pub fn metadata(&self) -> Option<&str> {
         if !self.metadata_finished { return None; }
         Some(&self.metadata)
@@ -30,26 +96,47 @@ impl<B: BufRead> MetadataReader<B> {
         if !self.metadata_finished { return None; }
         Some(&self.header)
     }
-}
 
-impl<B: BufRead> Read for MetadataReader<B> {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        let len = self.buffer.read(buf)?;
-        if len != 0 { return Ok(len); }
+    /// The GAF version declared in the metadata, if any.
+    pub fn gaf_version(&self) -> Option<GafVersion> {
+        self.metadata().and_then(GafVersion::from_metadata)
+    }
 
-        // If we finished reading from the metadata section, just
-        // continue reading from the inner reader.
-        if self.metadata_finished {
-            return self.reader.read(buf);
-        }
+    /// The number of lines (metadata and header) preceding the data.
+    ///
+    /// Line numbers reported by parsers reading from this reader are
+    /// relative to the data, so add this offset to find the line in the
+    /// original file (see `Error::with_line_offset`).
+    pub fn line_offset(&self) -> Option<u64> {
+        if !self.metadata_finished { return None; }
+        Some(self.metadata_lines)
+    }
 
-        loop {
+    /// Reads the metadata and header from the inner reader, stopping at the
+    /// first line of data.
+    ///
+    /// This happens automatically on the first read, but calling it ahead
+    /// of time makes `metadata` and `header` available before any records
+    /// are parsed, e.g. when streaming records straight into an exporter.
+    pub fn read_metadata(&mut self) -> std::io::Result<()> {
+        while !self.metadata_finished {
 
             // Read a line into the internal buffer
             let len = self.reader.read_line(&mut self.buffer.get_mut())?;
-            if len == 0 { return Ok(0); }
+            if len == 0 {
+                self.metadata_finished = true;
+                return Ok(());
+            }
 
             let line = self.buffer.get_ref().trim_start();
+            if !line.is_empty() && !line.starts_with('!') && !self.has_header {
+                // This line is data, so leave it in the buffer to be read
+                self.metadata_finished = true;
+                self.buffer.set_position(0);
+                return Ok(());
+            }
+            self.metadata_lines += 1;
+
             if line.is_empty() {
                 self.metadata.push('\n');
                 self.buffer.get_mut().clear();
@@ -64,13 +151,42 @@ impl<B: BufRead> Read for MetadataReader<B> {
                 // This line must be the header
                 self.buffer.read_to_string(&mut self.header)?;
                 self.buffer.get_mut().clear();
-                return self.reader.read(buf);
             }
         }
+        Ok(())
     }
 }
 
-#[derive(Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+impl<'a> MetadataReader<Box<dyn BufRead + 'a>> {
+
+    /// Creates a MetadataReader which transparently decompresses its input.
+    ///
+    /// The compression format (gzip, bzip2, or zstd) is detected from the
+    /// first bytes of the reader. Uncompressed input is read as-is.
+    pub fn decompress<R: BufRead + 'a>(mut reader: R) -> std::io::Result<Self> {
+        let compression = Compression::sniff(&mut reader)?;
+        Ok(MetadataReader::new(compression.decoder(reader)?))
+    }
+}
+
+impl<B: BufRead> Read for MetadataReader<B> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.buffer.read(buf)?;
+        if len != 0 { return Ok(len); }
+
+        // If we have not finished reading the metadata section, do so
+        // before reading any data.
+        if !self.metadata_finished {
+            self.read_metadata()?;
+            let len = self.buffer.read(buf)?;
+            if len != 0 { return Ok(len); }
+        }
+
+        self.reader.read(buf)
+    }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct AnnotationRecord {
     pub db: String,
     pub database_id: String,
@@ -92,21 +208,62 @@ pub struct AnnotationRecord {
 }
 
 impl AnnotationRecord {
-    pub fn parse_from<R: Read>(reader: R) -> Result<Vec<Self>, ()> {
-        let mut csv_reader = csv::ReaderBuilder::new()
-            .has_headers(false)
-            .delimiter(b'\t')
-            .flexible(true)
-            .from_reader(reader);
+    pub fn parse_from<R: Read>(reader: R) -> Result<Vec<Self>, Error> {
+        Self::iter_from(reader).collect()
+    }
 
-        let mut records = Vec::new();
-        let mut row = csv::StringRecord::new();
-        while csv_reader.read_record(&mut row).unwrap() {
-            let record: AnnotationRecord = row.deserialize(None).unwrap();
-            records.push(record);
+    /// Parses annotations like `parse_from`, but skips malformed rows.
+    ///
+    /// Each row that was skipped is reported in the returned Diagnostics.
+    /// Errors from the underlying reader still cause parsing to fail.
+    pub fn parse_lenient<R: Read>(reader: R) -> Result<(Vec<Self>, Diagnostics), Error> {
+        Self::iter_from(reader).lenient()
+    }
+
+    /// Creates an iterator which parses one annotation at a time.
+    pub fn iter_from<R: Read>(reader: R) -> Records<R, Self> {
+        Records::new(reader)
+    }
+}
+
+impl Record for AnnotationRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "DB",
+        "DB Object ID",
+        "DB Object Symbol",
+        "Qualifier",
+        "GO ID",
+        "DB:Reference",
+        "Evidence Code",
+        "With (or) From",
+        "Aspect",
+        "DB Object Name",
+        "DB Object Synonym",
+        "DB Object Type",
+        "Taxon",
+        "Date",
+        "Assigned By",
+        "Annotation Extension",
+        "Gene Product Form ID",
+    ];
+
+    /// Checks that GAF 2.2 files give a well-formed Qualifier with a
+    /// relation for every annotation. Older files are not checked, and a
+    /// relation which doesn't belong to the annotation's Aspect is left to
+    /// `ValidationPolicy`.
+    fn validate(&self, gaf_version: Option<GafVersion>) -> Result<(), (usize, ParseErrorKind)> {
+        const QUALIFIER: usize = 3;
+        if gaf_version < Some(GafVersion::V2_2) {
+            return Ok(());
         }
 
-        Ok(records)
+        let qualifier = Qualifier::try_from(&*self.invert)
+            .map_err(|e| (QUALIFIER, ParseErrorKind::InvalidValue(e)))?;
+        if qualifier.relation.is_none() {
+            let reason = "GAF 2.2 requires a relation in the qualifier".to_string();
+            return Err((QUALIFIER, ParseErrorKind::InvalidValue(reason)));
+        }
+        Ok(())
     }
 }
 
@@ -117,21 +274,464 @@ pub struct GeneRecord {
 }
 
 impl GeneRecord {
-    pub fn parse_from<R: Read>(reader: R) -> Result<Vec<Self>, ()> {
-        let mut csv_reader = csv::ReaderBuilder::new()
+    pub fn parse_from<R: Read>(reader: R) -> Result<Vec<Self>, Error> {
+        Self::iter_from(reader).collect()
+    }
+
+    /// Parses genes like `parse_from`, but skips malformed rows.
+    pub fn parse_lenient<R: Read>(reader: R) -> Result<(Vec<Self>, Diagnostics), Error> {
+        Self::iter_from(reader).lenient()
+    }
+
+    /// Creates an iterator which parses one gene at a time.
+    pub fn iter_from<R: Read>(reader: R) -> Records<R, Self> {
+        Records::new(reader)
+    }
+}
+
+impl Record for GeneRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "Gene ID",
+        "Gene Product Type",
+    ];
+}
+
+/// The gene product types used in GAF files, along with the Sequence
+/// Ontology (or other) classes which GPI files use in their place.
+const GENE_PRODUCT_TYPES: &[(&str, &str)] = &[
+    ("protein", "PR:000000001"),
+    ("gene", "SO:0000704"),
+    ("protein_coding_gene", "SO:0001217"),
+    ("mRNA", "SO:0000234"),
+    ("ncRNA", "SO:0000655"),
+    ("lncRNA", "SO:0001877"),
+    ("miRNA", "SO:0000276"),
+    ("rRNA", "SO:0000252"),
+    ("tRNA", "SO:0000253"),
+    ("snRNA", "SO:0000274"),
+    ("snoRNA", "SO:0000275"),
+    ("protein_complex", "GO:0032991"),
+];
+
+/// Converts a CURIE from the NCBITaxon ontology, as used in GPAD and GPI
+/// files, into the `taxon:` form used in GAF files.
+fn gaf_taxon(taxon: &str) -> String {
+    match taxon.strip_prefix("NCBITaxon:") {
+        Some(id) => format!("taxon:{}", id),
+        None => taxon.to_string(),
+    }
+}
+
+/// The reverse of `gaf_taxon`.
+fn ncbi_taxon(taxon: &str) -> String {
+    match taxon.strip_prefix("taxon:") {
+        Some(id) => format!("NCBITaxon:{}", id),
+        None => taxon.to_string(),
+    }
+}
+
+/// Joins a GAF DB and DB Object ID into the CURIE used by GPAD and GPI.
+fn curie(db: &str, id: &str) -> String {
+    if db.is_empty() { id.to_string() } else { format!("{}:{}", db, id) }
+}
+
+impl AnnotationRecord {
+    /// Converts a GPAD annotation into the GAF form used by `Annotation`.
+    ///
+    /// The gene product's symbol, name, synonyms, type, and taxon are taken
+    /// from its GPI entry, if one is given. Its CURIE is added to the
+    /// synonyms so that the annotation finds the Gene made by
+    /// `GeneRecord::from_gpi`, while the symbol and synonyms still match
+    /// genes which were read from other sources. ECO classes are replaced
+    /// by their GO evidence codes where the mapping has one.
+    ///
+    /// Returns None if the relation is not a known GO relation, which can
+    /// only happen for records that were not validated while parsing.
+    pub fn from_gpad(gpad: &GpadRecord, gpi: Option<&GpiRecord>, eco: &EcoMapping) -> Option<AnnotationRecord> {
+        let relation = Relation::from_ro_id(&gpad.relation)?;
+        let qualifier = if gpad.negation.is_empty() {
+            relation.to_string()
+        } else {
+            format!("NOT|{}", relation)
+        };
+
+        let (db, database_id) = gpad.db_object_id.split_once(':')
+            .unwrap_or(("", &gpad.db_object_id));
+
+        let mut gene_names = vec![&*gpad.db_object_id];
+        let mut taxon = String::new();
+        if let Some(gpi) = gpi {
+            gene_names.extend(gpi.db_object_synonyms.split('|').filter(|name| !name.is_empty()));
+            taxon = gaf_taxon(&gpi.db_object_taxon);
+        }
+        if !gpad.interacting_taxon.is_empty() {
+            let interacting_taxon = gaf_taxon(&gpad.interacting_taxon);
+            taxon = match taxon.is_empty() {
+                true => interacting_taxon,
+                false => format!("{}|{}", taxon, interacting_taxon),
+            };
+        }
+
+        // GPAD dates are YYYY-MM-DD, optionally followed by a time
+        let date = gpad.date.split('T').next().unwrap_or_default().replace('-', "");
+
+        Some(AnnotationRecord {
+            db: db.to_string(),
+            database_id: database_id.to_string(),
+            db_object_symbol: gpi.map_or(database_id, |gpi| &gpi.db_object_symbol).to_string(),
+            invert: qualifier,
+            go_term: gpad.go_term.to_string(),
+            reference: gpad.reference.to_string(),
+            evidence_code: eco.evidence_code(&gpad.evidence_type).unwrap_or(&gpad.evidence_type).to_string(),
+            additional_evidence: gpad.with_or_from.to_string(),
+            aspect: relation.aspect(),
+            unique_gene_name: gpi.map_or("", |gpi| &gpi.db_object_name).to_string(),
+            alternative_gene_name: gene_names.join("|"),
+            gene_product_type: gpi.map(GpiRecord::gene_product_type).unwrap_or_default().to_string(),
+            taxon,
+            date,
+            assigned_by: gpad.assigned_by.to_string(),
+            annotation_extension: gpad.annotation_extensions.to_string(),
+            gene_product_form_id: "".to_string(),
+        })
+    }
+}
+
+impl GeneRecord {
+    /// Converts a GPI gene product into a gene identified by its CURIE.
+    pub fn from_gpi(gpi: &GpiRecord) -> GeneRecord {
+        GeneRecord {
+            gene_id: gpi.db_object_id.to_string(),
+            gene_product_type: gpi.gene_product_type().to_string(),
+        }
+    }
+}
+
+impl GpadRecord {
+    /// Converts an annotation into a GPAD row.
+    ///
+    /// Annotations which don't give a relation (as in GAF files before
+    /// version 2.2) are given GO's default relation for their Aspect.
+    /// Evidence codes are replaced by their ECO classes (specific to the
+    /// annotation's references, if the mapping has such a class), and the
+    /// interacting taxon is taken from the second taxon of the Taxon column.
+    pub fn from_annotation(annotation: &Annotation, eco: &EcoMapping) -> GpadRecord {
+        let relation = annotation.qualifier.relation
+            .unwrap_or_else(|| Relation::default_for(annotation.aspect));
+
+        // GAF dates are YYYYMMDD, while GPAD dates are YYYY-MM-DD
+        let date = annotation.date;
+        let date = if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) {
+            format!("{}-{}-{}", &date[0..4], &date[4..6], &date[6..8])
+        } else {
+            date.to_string()
+        };
+
+        GpadRecord {
+            db_object_id: curie(annotation.db, annotation.database_id),
+            negation: if annotation.qualifier.negated { "NOT" } else { "" }.to_string(),
+            relation: relation.ro_id().to_string(),
+            go_term: annotation.go_term.to_string(),
+            reference: annotation.reference.to_string(),
+            evidence_type: eco.eco_class_for(annotation.evidence_code, annotation.reference).unwrap_or(annotation.evidence_code).to_string(),
+            with_or_from: annotation.additional_evidence.to_string(),
+            interacting_taxon: annotation.taxon.split('|').nth(1).map(ncbi_taxon).unwrap_or_default(),
+            date,
+            assigned_by: annotation.assigned_by.to_string(),
+            annotation_extensions: annotation.annotation_extension.to_string(),
+            annotation_properties: "".to_string(),
+        }
+    }
+}
+
+impl GpiRecord {
+    /// Describes the gene product of an annotation as a GPI row.
+    ///
+    /// The gene product's CURIE is left out of its synonyms, where it is
+    /// added by `AnnotationRecord::from_gpad`.
+    pub fn from_annotation(annotation: &Annotation) -> GpiRecord {
+        let db_object_id = curie(annotation.db, annotation.database_id);
+        let db_object_synonyms: Vec<&str> = annotation.record.alternative_gene_name.split('|')
+            .filter(|name| *name != db_object_id)
+            .collect();
+        let db_object_type = GENE_PRODUCT_TYPES.iter()
+            .find(|(name, _)| *name == annotation.gene_product_type)
+            .map_or(annotation.gene_product_type, |(_, class)| class);
+        let taxon = annotation.taxon.split('|').next().unwrap_or_default();
+
+        GpiRecord {
+            db_object_id,
+            db_object_symbol: annotation.db_object_symbol.to_string(),
+            db_object_name: annotation.record.unique_gene_name.to_string(),
+            db_object_synonyms: db_object_synonyms.join("|"),
+            db_object_type: db_object_type.to_string(),
+            db_object_taxon: ncbi_taxon(taxon),
+            encoded_by: "".to_string(),
+            parent_protein: "".to_string(),
+            protein_containing_complex_members: "".to_string(),
+            db_xrefs: "".to_string(),
+            gene_product_properties: "".to_string(),
+        }
+    }
+}
+
+/// A row of a GPAD 2.0 file, which annotates a gene product (identified by
+/// its CURIE) using Relation Ontology relations and ECO evidence classes.
+#[derive(Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct GpadRecord {
+    pub db_object_id: String,
+    pub negation: String,
+    pub relation: String,
+    pub go_term: String,
+    pub reference: String,
+    pub evidence_type: String,
+    pub with_or_from: String,
+    pub interacting_taxon: String,
+    pub date: String,
+    pub assigned_by: String,
+    pub annotation_extensions: String,
+    pub annotation_properties: String,
+}
+
+impl GpadRecord {
+    pub fn parse_from<R: Read>(reader: R) -> Result<Vec<Self>, Error> {
+        Self::iter_from(reader).collect()
+    }
+
+    /// Parses annotations like `parse_from`, but skips malformed rows.
+    pub fn parse_lenient<R: Read>(reader: R) -> Result<(Vec<Self>, Diagnostics), Error> {
+        Self::iter_from(reader).lenient()
+    }
+
+    /// Creates an iterator which parses one annotation at a time.
+    pub fn iter_from<R: Read>(reader: R) -> Records<R, Self> {
+        Records::new(reader)
+    }
+}
+
+impl Record for GpadRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "DB:DB_Object_ID",
+        "Negation",
+        "Relation",
+        "Ontology_Class_ID",
+        "Reference",
+        "Evidence_type",
+        "With_or_From",
+        "Interacting_taxon_ID",
+        "Date",
+        "Assigned_by",
+        "Annotation_Extensions",
+        "Annotation_Properties",
+    ];
+
+    /// Checks that the Negation column is either empty or `NOT`, and that
+    /// the relation is one used by GO.
+    fn validate(&self, _gaf_version: Option<GafVersion>) -> Result<(), (usize, ParseErrorKind)> {
+        const NEGATION: usize = 1;
+        const RELATION: usize = 2;
+        if !self.negation.is_empty() && self.negation != "NOT" {
+            let reason = format!("negation must be empty or 'NOT', found '{}'", self.negation);
+            return Err((NEGATION, ParseErrorKind::InvalidValue(reason)));
+        }
+        if Relation::from_ro_id(&self.relation).is_none() {
+            let reason = format!("unknown relation '{}'", self.relation);
+            return Err((RELATION, ParseErrorKind::InvalidValue(reason)));
+        }
+        Ok(())
+    }
+}
+
+/// A row of a GPI 2.0 file, which describes a gene product referenced by
+/// GPAD annotations.
+#[derive(Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct GpiRecord {
+    pub db_object_id: String,
+    pub db_object_symbol: String,
+    pub db_object_name: String,
+    pub db_object_synonyms: String,
+    pub db_object_type: String,
+    pub db_object_taxon: String,
+    pub encoded_by: String,
+    pub parent_protein: String,
+    pub protein_containing_complex_members: String,
+    pub db_xrefs: String,
+    pub gene_product_properties: String,
+}
+
+impl GpiRecord {
+    pub fn parse_from<R: Read>(reader: R) -> Result<Vec<Self>, Error> {
+        Self::iter_from(reader).collect()
+    }
+
+    /// Parses gene products like `parse_from`, but skips malformed rows.
+    pub fn parse_lenient<R: Read>(reader: R) -> Result<(Vec<Self>, Diagnostics), Error> {
+        Self::iter_from(reader).lenient()
+    }
+
+    /// Creates an iterator which parses one gene product at a time.
+    pub fn iter_from<R: Read>(reader: R) -> Records<R, Self> {
+        Records::new(reader)
+    }
+
+    /// The GAF name for this gene product's type (e.g. `protein` for
+    /// `PR:000000001`), or the type as given if it has no GAF name.
+    pub fn gene_product_type(&self) -> &str {
+        GENE_PRODUCT_TYPES.iter()
+            .find(|(_, class)| *class == self.db_object_type)
+            .map_or(&self.db_object_type, |(name, _)| name)
+    }
+}
+
+impl Record for GpiRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "DB:DB_Object_ID",
+        "DB_Object_Symbol",
+        "DB_Object_Name",
+        "DB_Object_Synonyms",
+        "DB_Object_Type",
+        "DB_Object_Taxon",
+        "Encoded_by",
+        "Parent_Protein",
+        "Protein_Containing_Complex_Members",
+        "DB_Xrefs",
+        "Gene_Product_Properties",
+    ];
+}
+
+/// An iterator which parses records from a reader one row at a time.
+///
+/// A single row buffer is reused for every record, so iterating over a
+/// file takes constant memory regardless of how large the file is.
+pub struct Records<R, T> {
+    csv_reader: csv::Reader<R>,
+    row: csv::ByteRecord,
+    gaf_version: Option<GafVersion>,
+    finished: bool,
+    record_type: PhantomData<T>,
+}
+
+impl<R: Read, T: Record> Records<R, T> {
+    fn new(reader: R) -> Records<R, T> {
+        let csv_reader = csv::ReaderBuilder::new()
             .has_headers(false)
             .delimiter(b'\t')
             .flexible(true)
             .from_reader(reader);
 
+        Records {
+            csv_reader,
+            row: csv::ByteRecord::new(),
+            gaf_version: None,
+            finished: false,
+            record_type: PhantomData,
+        }
+    }
+
+    /// Validates records according to the rules of the given GAF version,
+    /// e.g. as found by `MetadataReader::gaf_version`.
+    pub fn gaf_version(mut self, gaf_version: Option<GafVersion>) -> Self {
+        self.gaf_version = gaf_version;
+        self
+    }
+
+    /// Parses the remaining records, skipping malformed rows.
+    ///
+    /// Each row that was skipped is reported in the returned Diagnostics.
+    /// Errors from the underlying reader still cause parsing to fail.
+    pub fn lenient(self) -> Result<(Vec<T>, Diagnostics), Error> {
         let mut records = Vec::new();
-        let mut row = csv::StringRecord::new();
-        while csv_reader.read_record(&mut row).unwrap() {
-            let record: GeneRecord = row.deserialize(None).unwrap();
-            records.push(record);
+        let mut diagnostics = Diagnostics::new();
+        for result in self {
+            match result {
+                Ok(record) => records.push(record),
+                Err(Error::Parse(error)) => diagnostics.push(Diagnostic::SkippedRow(error)),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok((records, diagnostics))
+    }
+}
+
+impl<R: Read, T: Record> Iterator for Records<R, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished { return None; }
+
+        match self.csv_reader.read_byte_record(&mut self.row) {
+            Ok(true) => Some(parse_row(&self.row, self.gaf_version).map_err(Error::Parse)),
+            Ok(false) => {
+                self.finished = true;
+                None
+            }
+            Err(e) => {
+                // The reader can't be trusted to make progress after an I/O error
+                self.finished = true;
+                Some(Err(Error::Io(e.into())))
+            }
+        }
+    }
+}
+
+/// Parses and validates a single row, describing what went wrong if it
+/// is malformed.
+fn parse_row<T: Record>(
+    row: &csv::ByteRecord,
+    gaf_version: Option<GafVersion>,
+) -> Result<T, ParseError> {
+    let columns = T::COLUMNS;
+    let error = |column: Option<usize>, cause: ParseErrorKind| {
+        let row_text: Vec<_> = row.iter().map(String::from_utf8_lossy).collect();
+        ParseError {
+            line: row.position().map(|position| position.line()).unwrap_or(0),
+            column: column.and_then(|i| columns.get(i)).copied(),
+            row: row_text.join("\t"),
+            cause,
         }
+    };
+
+    if row.len() < columns.len() {
+        let cause = ParseErrorKind::ColumnCount { expected: columns.len(), found: row.len() };
+        return Err(error(None, cause));
+    }
+
+    if let Some(i) = row.iter().position(|field| std::str::from_utf8(field).is_err()) {
+        return Err(error(Some(i), ParseErrorKind::InvalidUtf8));
+    }
 
-        Ok(records)
+    let mut fields = RowFields { fields: row.iter(), current: None };
+    let record = T::deserialize(SeqAccessDeserializer::new(&mut fields))
+        .map_err(|e| error(fields.current, ParseErrorKind::InvalidValue(e.to_string())))?;
+
+    record.validate(gaf_version)
+        .map_err(|(column, cause)| error(Some(column), cause))?;
+    Ok(record)
+}
+
+/// Hands the fields of a row to serde one at a time.
+///
+/// The csv crate's own deserializer does not report which field a custom
+/// error (such as an unknown Aspect letter) came from, so we keep track
+/// of the field being deserialized ourselves.
+struct RowFields<'r> {
+    fields: csv::ByteRecordIter<'r>,
+    current: Option<usize>,
+}
+
+impl<'de> SeqAccess<'de> for &mut RowFields<'_> {
+    type Error = serde::de::value::Error;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, Self::Error>
+        where S: DeserializeSeed<'de>
+    {
+        let field = match self.fields.next() {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        self.current = Some(self.current.map_or(0, |i| i + 1));
+        let field = std::str::from_utf8(field).map_err(serde::de::Error::custom)?;
+        seed.deserialize(field.into_deserializer()).map(Some)
     }
 }
 
@@ -139,6 +739,7 @@ impl GeneRecord {
 mod tests {
     use super::*;
     use std::io::Cursor;
+    use crate::EvidencePolicy;
 
     #[test]
     fn test_parse_annotation() {
@@ -179,6 +780,200 @@ mod tests {
         assert_eq!(vec![expected], genes);
     }
 
+    #[test]
+    fn test_parse_annotation_invalid_aspect() {
+        let input = r"!gaf-version: 2.1
+DB	DB Object ID	DB Object Symbol	Qualifier	GO ID	DB:Reference (JDB:Reference)	Evidence Code	With (or) From	Aspect	DB Object Name	DB Object Type	Taxon	Date	Assigned By	Annotation Extension	Gene Product Form ID
+TAIR	locus:2031476	ENO1		GO:0000015	TAIR:AnalysisReference:501756966	IEA	InterPro:IPR000941	C	AT1G74030	AT1G74030|ENO1|enolase 1|F2P9.10|F2P9_10	protein	taxon:3702	20190907	InterPro		TAIR:locus:2031476
+TAIR	locus:2043067	ENOC		GO:0000015	TAIR:AnalysisReference:501756966	IEA	InterPro:IPR000941	X	AT2G29560	AT2G29560|ENOC	protein	taxon:3702	20190408	InterPro		TAIR:locus:2043067";
+        let mut reader = MetadataReader::new(Cursor::new(input));
+        let error = AnnotationRecord::parse_from(&mut reader).unwrap_err()
+            .with_line_offset(reader.line_offset().unwrap());
+        let error = match error {
+            Error::Parse(error) => error,
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+        assert_eq!(error.line, 4);
+        assert_eq!(error.column, Some("Aspect"));
+        assert!(error.row.starts_with("TAIR\tlocus:2043067\tENOC"));
+        assert!(matches!(error.cause, ParseErrorKind::InvalidValue(_)));
+    }
+
+    #[test]
+    fn test_parse_gene_missing_column() {
+        let input = "AT1G01010	protein_coding\nAT1G01020\n";
+        let error = GeneRecord::parse_from(Cursor::new(input)).unwrap_err();
+        let error = match error {
+            Error::Parse(error) => error,
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+        assert_eq!(error.line, 2);
+        assert_eq!(error.column, None);
+        assert_eq!(error.row, "AT1G01020");
+        assert_eq!(error.cause, ParseErrorKind::ColumnCount { expected: 2, found: 1 });
+    }
+
+    #[test]
+    fn test_parse_annotation_lenient() {
+        let input = b"TAIR\tlocus:2031476\tENO1\t\tGO:0000015\tTAIR:AnalysisReference:501756966\tIEA\tInterPro:IPR000941\tC\tAT1G74030\tAT1G74030|ENO1\tprotein\ttaxon:3702\t20190907\tInterPro\t\tTAIR:locus:2031476
+TAIR\tlocus:2043067\tENOC\t\tGO:0000015\tTAIR:AnalysisReference:501756966\tIEA\tInterPro:IPR000941\tX\tAT2G29560\tAT2G29560|ENOC\tprotein\ttaxon:3702\t20190408\tInterPro\t\tTAIR:locus:2043067
+TAIR\tlocus:2044851\tLOS2\t\tGO:0000015
+TAIR\tlocus:2032970\tAT1G25260\t\tGO:0000027\tTAIR:AnalysisReference:501756966\tIEA\tInterPro:IPR033867\tP\tAT1G25260\tAT1G25260|F4F7.35|\xff\tprotein\ttaxon:3702\t20190404\tInterPro\t\tTAIR:locus:2032970
+TAIR\tlocus:2017963\tTUN\t\tGO:0000030\tTAIR:AnalysisReference:501756966\tIEA\tInterPro:IPR026051\tF\tAT1G16570\tAT1G16570|TUN\tprotein\ttaxon:3702\t20190907\tInterPro\t\tTAIR:locus:2017963
+";
+        let (records, diagnostics) = AnnotationRecord::parse_lenient(Cursor::new(&input[..])).unwrap();
+        let genes: Vec<_> = records.iter().map(|record| &*record.unique_gene_name).collect();
+        assert_eq!(genes, vec!["AT1G74030", "AT1G16570"]);
+
+        let skipped: Vec<_> = diagnostics.skipped_rows()
+            .map(|error| (error.line, error.column, error.cause.clone()))
+            .collect();
+        assert_eq!(skipped, vec![
+            (2, Some("Aspect"), ParseErrorKind::InvalidValue("unknown variant `X`, expected one of `F`, `P`, `C`".to_string())),
+            (3, None, ParseErrorKind::ColumnCount { expected: 17, found: 5 }),
+            (4, Some("DB Object Synonym"), ParseErrorKind::InvalidUtf8),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_gaf_2_2_qualifiers() {
+        let input = r"!gaf-version: 2.2
+!generated-by: TAIR
+DB	DB Object ID	DB Object Symbol	Qualifier	GO ID	DB:Reference	Evidence Code	With (or) From	Aspect	DB Object Name	DB Object Synonym	DB Object Type	Taxon	Date	Assigned By	Annotation Extension	Gene Product Form ID
+TAIR	locus:2031476	ENO1	located_in	GO:0000015	TAIR:AnalysisReference:501756966	IEA	InterPro:IPR000941	C	AT1G74030	AT1G74030|ENO1	protein	taxon:3702	20190907	InterPro		TAIR:locus:2031476
+TAIR	locus:2017963	TUN	NOT|enables	GO:0000030	TAIR:AnalysisReference:501756966	IEA	InterPro:IPR026051	F	AT1G16570	AT1G16570|TUN	protein	taxon:3702	20190907	InterPro		TAIR:locus:2017963
+TAIR	locus:2032970	AT1G25260	enables	GO:0000027	TAIR:AnalysisReference:501756966	IEA	InterPro:IPR033867	P	AT1G25260	AT1G25260|F4F7.35	protein	taxon:3702	20190404	InterPro		TAIR:locus:2032970
+TAIR	locus:2064821	CTEXP		GO:0000049	TAIR:Publication:501746929	IDA		F	AT2G40730	AT2G40730|CTEXP	protein	taxon:3702	20120106	TAIR		TAIR:locus:2064821
+";
+        let mut reader = MetadataReader::new(Cursor::new(input));
+        reader.read_metadata().unwrap();
+        assert_eq!(reader.gaf_version(), Some(GafVersion::V2_2));
+
+        let version = reader.gaf_version();
+        let (records, diagnostics) = AnnotationRecord::iter_from(&mut reader)
+            .gaf_version(version)
+            .lenient()
+            .unwrap();
+        let symbols: Vec<_> = records.iter().map(|record| &*record.db_object_symbol).collect();
+        assert_eq!(symbols, vec!["ENO1", "TUN", "AT1G25260"]);
+
+        let skipped: Vec<_> = diagnostics.skipped_rows()
+            .map(|error| (error.line, error.column, error.cause.clone()))
+            .collect();
+        assert_eq!(skipped, vec![
+            (4, Some("Qualifier"), ParseErrorKind::InvalidValue("GAF 2.2 requires a relation in the qualifier".to_string())),
+        ]);
+
+        // Qualifiers of older files are not checked
+        let input = input.replace("gaf-version: 2.2", "gaf-version: 2.1").replace("NOT|enables", "NOT|binds");
+        let mut reader = MetadataReader::new(Cursor::new(input));
+        reader.read_metadata().unwrap();
+        let version = reader.gaf_version();
+        let records: Vec<AnnotationRecord> = AnnotationRecord::iter_from(&mut reader)
+            .gaf_version(version)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 4);
+        assert!(Annotation::from_record(&records[1], &EvidencePolicy::default()).invert);
+    }
+
+    #[test]
+    fn test_parse_gpad_and_gpi() {
+        let gpad = "!gpad-version: 2.0
+!generated-by: TAIR
+TAIR:locus:2031476		RO:0001025	GO:0000015	TAIR:AnalysisReference:501756966	ECO:0000501	InterPro:IPR000941		2019-09-07	InterPro		
+TAIR:locus:2017963	NOT	RO:0002327	GO:0000030	PMID:12345	ECO:0000314			2019-09-07	TAIR		
+TAIR:locus:2032970		RO:0002331	GO:0000027	PMID:12345	ECO:0000314
+TAIR:locus:2064821		RO:9999999	GO:0000049	PMID:12345	ECO:0000314			2012-01-06	TAIR		
+";
+        let gpi = "!gpi-version: 2.0
+TAIR:locus:2031476	ENO1	enolase 1	AT1G74030|F2P9.10	PR:000000001	NCBITaxon:3702					
+TAIR:locus:2017963	TUN	tunicamycin induced 1	AT1G16570	SO:0000655	NCBITaxon:3702					
+";
+        let mut gpad_reader = MetadataReader::new(Cursor::new(gpad)).without_header();
+        gpad_reader.read_metadata().unwrap();
+        assert_eq!(gpad_reader.line_offset(), Some(2));
+        let (gpad_records, diagnostics) = GpadRecord::parse_lenient(&mut gpad_reader).unwrap();
+        assert_eq!(gpad_reader.metadata(), Some("!gpad-version: 2.0\n!generated-by: TAIR\n"));
+        assert_eq!(gpad_reader.header(), Some(""));
+
+        let skipped: Vec<_> = diagnostics.skipped_rows()
+            .map(|error| (error.line, error.column, error.cause.clone()))
+            .collect();
+        assert_eq!(skipped, vec![
+            (3, None, ParseErrorKind::ColumnCount { expected: 12, found: 6 }),
+            (4, Some("Relation"), ParseErrorKind::InvalidValue("unknown relation 'RO:9999999'".to_string())),
+        ]);
+
+        let gpi_reader = MetadataReader::new(Cursor::new(gpi)).without_header();
+        let gpi_records = GpiRecord::parse_from(gpi_reader).unwrap();
+        let genes: Vec<_> = gpi_records.iter().map(GeneRecord::from_gpi).collect();
+        assert_eq!(genes, vec![
+            GeneRecord { gene_id: "TAIR:locus:2031476".to_string(), gene_product_type: "protein".to_string() },
+            GeneRecord { gene_id: "TAIR:locus:2017963".to_string(), gene_product_type: "ncRNA".to_string() },
+        ]);
+
+        let eco = EcoMapping::default();
+        let annotation = AnnotationRecord::from_gpad(&gpad_records[0], Some(&gpi_records[0]), &eco).unwrap();
+        assert_eq!(annotation, AnnotationRecord {
+            db: "TAIR".to_string(),
+            database_id: "locus:2031476".to_string(),
+            db_object_symbol: "ENO1".to_string(),
+            invert: "located_in".to_string(),
+            go_term: "GO:0000015".to_string(),
+            reference: "TAIR:AnalysisReference:501756966".to_string(),
+            evidence_code: "IEA".to_string(),
+            additional_evidence: "InterPro:IPR000941".to_string(),
+            aspect: Aspect::CellularComponent,
+            unique_gene_name: "enolase 1".to_string(),
+            alternative_gene_name: "TAIR:locus:2031476|AT1G74030|F2P9.10".to_string(),
+            gene_product_type: "protein".to_string(),
+            taxon: "taxon:3702".to_string(),
+            date: "20190907".to_string(),
+            assigned_by: "InterPro".to_string(),
+            annotation_extension: "".to_string(),
+            gene_product_form_id: "".to_string(),
+        });
+
+        let annotation = AnnotationRecord::from_gpad(&gpad_records[1], None, &eco).unwrap();
+        assert_eq!(annotation.invert, "NOT|enables");
+        assert_eq!(annotation.aspect, Aspect::MolecularFunction);
+        assert_eq!(annotation.evidence_code, "IDA");
+        assert_eq!(annotation.db_object_symbol, "locus:2017963");
+    }
+
+    #[test]
+    fn test_parse_gpad_without_gpi() {
+        let gpad = "!gpad-version: 2.0
+TAIR:locus:2031476		RO:0002331	GO:0009615	PMID:12345	ECO:0000315		NCBITaxon:12242	2019-09-07	TAIR		
+";
+        let mut gpad_reader = MetadataReader::new(Cursor::new(gpad)).without_header();
+        gpad_reader.read_metadata().unwrap();
+        let gpad_records = GpadRecord::parse_from(&mut gpad_reader).unwrap();
+
+        let annotation = AnnotationRecord::from_gpad(&gpad_records[0], None, &EcoMapping::default()).unwrap();
+        assert_eq!(annotation.taxon, "taxon:12242");
+        assert_eq!(annotation.unique_gene_name, "");
+        assert_eq!(annotation.alternative_gene_name, "TAIR:locus:2031476");
+        assert_eq!(annotation.gene_product_type, "");
+    }
+
+    #[test]
+    fn test_metadata_reader_decompress() {
+        let input = "!gaf-version: 2.1\nname\tgene_model_type\nAT1G01010\tprotein_coding\n";
+        let mut compressed = Vec::new();
+        Compression::Gzip.encode(&mut compressed, |writer| writer.write_all(input.as_bytes())).unwrap();
+
+        let mut reader = MetadataReader::decompress(Cursor::new(compressed)).unwrap();
+        let genes = GeneRecord::parse_from(&mut reader).unwrap();
+        assert_eq!(reader.metadata(), Some("!gaf-version: 2.1\n"));
+        assert_eq!(reader.header(), Some("name\tgene_model_type\n"));
+        assert_eq!(genes, vec![GeneRecord {
+            gene_id: "AT1G01010".to_string(),
+            gene_product_type: "protein_coding".to_string(),
+        }]);
+    }
+
     #[test]
     fn test_metadata_reader() {
         let input = r"
@@ -8,17 +8,48 @@ extern crate lazy_static;
 use std::convert::TryFrom;
 use serde::{Deserialize, Serialize};
 
+mod error;
+mod diagnostics;
+mod compression;
+mod obo;
+mod eco;
+mod evidence;
 mod ingest;
 mod models;
+mod ontology;
+mod validation;
+mod aliases;
 mod index;
+mod propagation;
+mod slim;
+mod enrichment;
+mod summary;
+mod combinations;
+mod profile;
 mod queries;
+mod query_language;
 mod export;
 
-pub use ingest::{AnnotationRecord, GeneRecord, MetadataReader};
-pub use models::{Annotation, Gene};
-pub use index::Index;
-pub use queries::{Segment, Query, QueryResult};
-pub use export::GafExporter;
+pub use error::{Error, ParseError, ParseErrorKind};
+pub use diagnostics::{Diagnostic, Diagnostics};
+pub use compression::Compression;
+pub use eco::{EcoMapping, EcoOntology};
+pub use evidence::EvidencePolicy;
+pub use ingest::{AnnotationRecord, GeneRecord, GpadRecord, GpiRecord, MetadataReader, Records, Record, GafVersion};
+pub use models::{Annotation, Gene, Qualifier, Relation};
+pub use ontology::{Ontology, Term};
+pub use validation::{ValidationPolicy, normalize_terms};
+pub use aliases::AliasIndex;
+pub use index::{Index, IndexOptions, NegationPolicy, SegmentCounts};
+pub use propagation::TermIndex;
+pub use slim::{SlimMapping, SlimCount};
+pub use enrichment::{Enrichment, write_enrichment_tsv, bonferroni, benjamini_hochberg};
+pub use summary::{Summary, SegmentSummary};
+pub use combinations::{StatusCombination, CombinationCount, CombinationMatrix};
+pub use profile::GeneProfile;
+pub use queries::{Segment, Predicate, AnnotationFilter, Query, QueryResult};
+pub use query_language::QueryError;
+pub use export::{GafExporter, GpadExporter};
 
 #[derive(Debug, Hash, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Aspect {
@@ -30,6 +61,24 @@ pub enum Aspect {
     CellularComponent,
 }
 
+impl Aspect {
+    /// Every Aspect, in the order they are written in summaries and profiles.
+    pub const ALL: &'static [Aspect] = &[
+        Aspect::MolecularFunction,
+        Aspect::BiologicalProcess,
+        Aspect::CellularComponent,
+    ];
+
+    /// The letter for this Aspect, as in a GAF file.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Aspect::MolecularFunction => "F",
+            Aspect::BiologicalProcess => "P",
+            Aspect::CellularComponent => "C",
+        }
+    }
+}
+
 impl TryFrom<&str> for Aspect {
     type Error = ();
 
@@ -46,10 +95,30 @@ impl TryFrom<&str> for Aspect {
 
 #[derive(Debug, Hash, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum AnnotationStatus {
+    #[serde(rename = "EXP")]
     KnownExperimental,
+    #[serde(rename = "OTHER")]
     KnownOther,
+    #[serde(rename = "UNKNOWN")]
     Unknown,
+    #[serde(rename = "UNANNOTATED")]
     Unannotated,
+    /// Only annotated with `NOT` annotations, see `NegationPolicy::Separate`.
+    #[serde(rename = "NEGATED")]
+    Negated,
+}
+
+impl AnnotationStatus {
+    /// The name of this status, as used in segments like `F,EXP`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnnotationStatus::KnownExperimental => "EXP",
+            AnnotationStatus::KnownOther => "OTHER",
+            AnnotationStatus::Unknown => "UNKNOWN",
+            AnnotationStatus::Unannotated => "UNANNOTATED",
+            AnnotationStatus::Negated => "NEGATED",
+        }
+    }
 }
 
 impl TryFrom<&str> for AnnotationStatus {
@@ -61,6 +130,7 @@ impl TryFrom<&str> for AnnotationStatus {
             "OTHER" => AnnotationStatus::KnownOther,
             "UNKNOWN" => AnnotationStatus::Unknown,
             "UNANNOTATED" => AnnotationStatus::Unannotated,
+            "NEGATED" => AnnotationStatus::Negated,
             _ => return Err(()),
         };
         Ok(status)
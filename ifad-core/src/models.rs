@@ -1,5 +1,177 @@
-use crate::{Aspect, AnnotationStatus, AnnotationRecord, GeneRecord};
-use crate::index::AnnoIndex;
+use std::convert::TryFrom;
+use std::fmt;
+use crate::{Aspect, AnnotationStatus, AnnotationRecord, GeneRecord, EvidencePolicy};
+
+/// A relation between a gene product and a GO term, as given in the
+/// Qualifier column of GAF 2.2.
+#[derive(Debug, Hash, Copy, Clone, Eq, PartialEq)]
+pub enum Relation {
+    Enables,
+    ContributesTo,
+    InvolvedIn,
+    ActsUpstreamOf,
+    ActsUpstreamOfPositiveEffect,
+    ActsUpstreamOfNegativeEffect,
+    ActsUpstreamOfOrWithin,
+    ActsUpstreamOfOrWithinPositiveEffect,
+    ActsUpstreamOfOrWithinNegativeEffect,
+    LocatedIn,
+    PartOf,
+    IsActiveIn,
+    ColocalizesWith,
+}
+
+impl Relation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Relation::Enables => "enables",
+            Relation::ContributesTo => "contributes_to",
+            Relation::InvolvedIn => "involved_in",
+            Relation::ActsUpstreamOf => "acts_upstream_of",
+            Relation::ActsUpstreamOfPositiveEffect => "acts_upstream_of_positive_effect",
+            Relation::ActsUpstreamOfNegativeEffect => "acts_upstream_of_negative_effect",
+            Relation::ActsUpstreamOfOrWithin => "acts_upstream_of_or_within",
+            Relation::ActsUpstreamOfOrWithinPositiveEffect => "acts_upstream_of_or_within_positive_effect",
+            Relation::ActsUpstreamOfOrWithinNegativeEffect => "acts_upstream_of_or_within_negative_effect",
+            Relation::LocatedIn => "located_in",
+            Relation::PartOf => "part_of",
+            Relation::IsActiveIn => "is_active_in",
+            Relation::ColocalizesWith => "colocalizes_with",
+        }
+    }
+
+    /// The Relation Ontology class for this relation, as used in GPAD files.
+    pub fn ro_id(&self) -> &'static str {
+        match self {
+            Relation::Enables => "RO:0002327",
+            Relation::ContributesTo => "RO:0002326",
+            Relation::InvolvedIn => "RO:0002331",
+            Relation::ActsUpstreamOf => "RO:0002263",
+            Relation::ActsUpstreamOfPositiveEffect => "RO:0004034",
+            Relation::ActsUpstreamOfNegativeEffect => "RO:0004035",
+            Relation::ActsUpstreamOfOrWithin => "RO:0002264",
+            Relation::ActsUpstreamOfOrWithinPositiveEffect => "RO:0004032",
+            Relation::ActsUpstreamOfOrWithinNegativeEffect => "RO:0004033",
+            Relation::LocatedIn => "RO:0001025",
+            Relation::PartOf => "BFO:0000050",
+            Relation::IsActiveIn => "RO:0002432",
+            Relation::ColocalizesWith => "RO:0002325",
+        }
+    }
+
+    /// The relation which GO assumes for annotations to an Aspect which
+    /// don't give one, as in GAF files before version 2.2.
+    ///
+    /// GO uses `part_of` rather than `located_in` for protein-containing
+    /// complexes, but telling those apart requires the ontology.
+    pub fn default_for(aspect: Aspect) -> Relation {
+        match aspect {
+            Aspect::MolecularFunction => Relation::Enables,
+            Aspect::BiologicalProcess => Relation::InvolvedIn,
+            Aspect::CellularComponent => Relation::LocatedIn,
+        }
+    }
+
+    /// Finds the relation with the given Relation Ontology class.
+    pub fn from_ro_id(ro_id: &str) -> Option<Relation> {
+        const RELATIONS: &[Relation] = &[
+            Relation::Enables,
+            Relation::ContributesTo,
+            Relation::InvolvedIn,
+            Relation::ActsUpstreamOf,
+            Relation::ActsUpstreamOfPositiveEffect,
+            Relation::ActsUpstreamOfNegativeEffect,
+            Relation::ActsUpstreamOfOrWithin,
+            Relation::ActsUpstreamOfOrWithinPositiveEffect,
+            Relation::ActsUpstreamOfOrWithinNegativeEffect,
+            Relation::LocatedIn,
+            Relation::PartOf,
+            Relation::IsActiveIn,
+            Relation::ColocalizesWith,
+        ];
+        RELATIONS.iter().copied().find(|relation| relation.ro_id() == ro_id)
+    }
+
+    /// The Aspect of the GO terms which this relation may be used with.
+    pub fn aspect(&self) -> Aspect {
+        match self {
+            Relation::Enables | Relation::ContributesTo => Aspect::MolecularFunction,
+            Relation::InvolvedIn
+            | Relation::ActsUpstreamOf
+            | Relation::ActsUpstreamOfPositiveEffect
+            | Relation::ActsUpstreamOfNegativeEffect
+            | Relation::ActsUpstreamOfOrWithin
+            | Relation::ActsUpstreamOfOrWithinPositiveEffect
+            | Relation::ActsUpstreamOfOrWithinNegativeEffect => Aspect::BiologicalProcess,
+            Relation::LocatedIn
+            | Relation::PartOf
+            | Relation::IsActiveIn
+            | Relation::ColocalizesWith => Aspect::CellularComponent,
+        }
+    }
+}
+
+impl TryFrom<&str> for Relation {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let relation = match value {
+            "enables" => Relation::Enables,
+            "contributes_to" => Relation::ContributesTo,
+            "involved_in" => Relation::InvolvedIn,
+            "acts_upstream_of" => Relation::ActsUpstreamOf,
+            "acts_upstream_of_positive_effect" => Relation::ActsUpstreamOfPositiveEffect,
+            "acts_upstream_of_negative_effect" => Relation::ActsUpstreamOfNegativeEffect,
+            "acts_upstream_of_or_within" => Relation::ActsUpstreamOfOrWithin,
+            "acts_upstream_of_or_within_positive_effect" => Relation::ActsUpstreamOfOrWithinPositiveEffect,
+            "acts_upstream_of_or_within_negative_effect" => Relation::ActsUpstreamOfOrWithinNegativeEffect,
+            "located_in" => Relation::LocatedIn,
+            "part_of" => Relation::PartOf,
+            "is_active_in" => Relation::IsActiveIn,
+            "colocalizes_with" => Relation::ColocalizesWith,
+            _ => return Err(()),
+        };
+        Ok(relation)
+    }
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The parsed contents of a GAF Qualifier column, e.g. `NOT|enables`.
+///
+/// Before GAF 2.2 the Qualifier column was usually empty, so the relation
+/// is optional.
+#[derive(Debug, Default, Hash, Copy, Clone, Eq, PartialEq)]
+pub struct Qualifier {
+    pub negated: bool,
+    pub relation: Option<Relation>,
+}
+
+impl TryFrom<&str> for Qualifier {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut qualifier = Qualifier::default();
+        for part in value.split('|').filter(|part| !part.is_empty()) {
+            if part.eq_ignore_ascii_case("not") {
+                qualifier.negated = true;
+                continue;
+            }
+
+            let relation = Relation::try_from(part)
+                .map_err(|_| format!("unknown qualifier '{}'", part))?;
+            if qualifier.relation.is_some() {
+                return Err(format!("more than one relation in qualifier '{}'", value));
+            }
+            qualifier.relation = Some(relation);
+        }
+        Ok(qualifier)
+    }
+}
 
 #[derive(Debug, Hash, Eq, PartialEq)]
 pub struct Annotation<'a> {
@@ -7,7 +179,9 @@ pub struct Annotation<'a> {
     pub db: &'a str,
     pub database_id: &'a str,
     pub db_object_symbol: &'a str,
+    /// Whether this is a `NOT` annotation. Equal to `qualifier.negated`.
     pub invert: bool,
+    pub qualifier: Qualifier,
     pub go_term: &'a str,
     pub reference: &'a str,
     pub evidence_code: &'a str,
@@ -24,24 +198,27 @@ pub struct Annotation<'a> {
 }
 
 impl Annotation<'_> {
-    pub fn from_record<'a>(record: &'a AnnotationRecord, experimental_evidence: &[&str]) -> Annotation<'a> {
+    /// Creates an Annotation from a record, classifying it by its evidence
+    /// code according to the given policy.
+    pub fn from_record<'a>(record: &'a AnnotationRecord, evidence: &EvidencePolicy) -> Annotation<'a> {
         let mut gene_names = vec![&*record.unique_gene_name];
         gene_names.extend(record.alternative_gene_name.split('|'));
 
-        let annotation_status =
-            if &record.evidence_code == "ND" {
-                AnnotationStatus::Unknown
-            } else if experimental_evidence.contains(&&*record.evidence_code) {
-                AnnotationStatus::KnownExperimental
-            } else {
-                AnnotationStatus::KnownOther
-            };
+        let annotation_status = evidence.status_of(&record.evidence_code);
+
+        // Qualifiers are only validated while parsing GAF 2.2, so one which
+        // is not understood still gives whether the annotation is negated
+        let qualifier = Qualifier::try_from(&*record.invert).unwrap_or_else(|_| Qualifier {
+            negated: record.invert.split('|').any(|part| part.eq_ignore_ascii_case("not")),
+            relation: None,
+        });
 
         Annotation {
             db: &record.db,
             database_id: &record.database_id,
             db_object_symbol: &record.db_object_symbol,
-            invert: record.invert.to_lowercase() == "not",
+            invert: qualifier.negated,
+            qualifier,
             go_term: &record.go_term,
             reference: &record.reference,
             evidence_code: &record.evidence_code,
@@ -58,13 +235,6 @@ impl Annotation<'_> {
             record
         }
     }
-
-    pub fn gene_in<'a>(&self, index: &'a AnnoIndex) -> Option<&Gene<'a>> {
-        self.gene_names.iter()
-            .find(|name| index.contains_key(&((**name).to_string())))
-            .and_then(|name| index.get(&(**name).to_string()).map(|(gene, _)| gene))
-            .copied()
-    }
 }
 
 #[derive(Debug, Hash, Eq, PartialEq)]
@@ -75,7 +245,7 @@ pub struct Gene<'a> {
 }
 
 impl Gene<'_> {
-    pub fn from_record(record: &GeneRecord) -> Gene {
+    pub fn from_record(record: &GeneRecord) -> Gene<'_> {
         Gene {
             record,
             gene_id: &record.gene_id,
@@ -109,12 +279,15 @@ mod tests {
             annotation_extension: "".to_string(),
             gene_product_form_id: "TAIR:locus:2031476".to_string(),
         };
-        let annotation = Annotation::from_record(&record, &["IEA"]);
+        let mut evidence = EvidencePolicy::default();
+        evidence.set_experimental(vec!["IEA".to_string()]);
+        let annotation = Annotation::from_record(&record, &evidence);
         let expected_annotation = Annotation {
             db: "TAIR",
             database_id: "locus:2031476",
             db_object_symbol: "ENO1",
             invert: false,
+            qualifier: Qualifier::default(),
             go_term: "GO:0000015",
             reference: "TAIR:AnalysisReference:501756966",
             evidence_code: "IEA",
@@ -139,4 +312,30 @@ mod tests {
         };
         assert_eq!(annotation, expected_annotation);
     }
+
+    #[test]
+    fn test_parse_qualifier() {
+        let qualifier = Qualifier::try_from("NOT|enables").unwrap();
+        assert_eq!(qualifier, Qualifier { negated: true, relation: Some(Relation::Enables) });
+        assert_eq!(qualifier.relation.unwrap().aspect(), Aspect::MolecularFunction);
+
+        let qualifier = Qualifier::try_from("colocalizes_with").unwrap();
+        assert_eq!(qualifier, Qualifier { negated: false, relation: Some(Relation::ColocalizesWith) });
+
+        let qualifier = Qualifier::try_from("not").unwrap();
+        assert_eq!(qualifier, Qualifier { negated: true, relation: None });
+
+        assert_eq!(Qualifier::try_from("").unwrap(), Qualifier::default());
+        assert!(Qualifier::try_from("NOT|binds").is_err());
+        assert!(Qualifier::try_from("enables|involved_in").is_err());
+    }
+
+    #[test]
+    fn test_relation_ro_ids() {
+        assert_eq!(Relation::from_ro_id("RO:0002327"), Some(Relation::Enables));
+        assert_eq!(Relation::from_ro_id("BFO:0000050"), Some(Relation::PartOf));
+        assert_eq!(Relation::from_ro_id(Relation::ActsUpstreamOfOrWithinNegativeEffect.ro_id()),
+                   Some(Relation::ActsUpstreamOfOrWithinNegativeEffect));
+        assert_eq!(Relation::from_ro_id("RO:9999999"), None);
+    }
 }
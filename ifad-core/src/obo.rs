@@ -0,0 +1,118 @@
+use std::io::BufRead;
+use crate::error::{Error, ParseError, ParseErrorKind};
+
+/// A `[Term]` (or other) stanza of an OBO file, holding its tag-value
+/// pairs in the order they were written.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Stanza {
+    pub kind: String,
+    pub tags: Vec<(String, String)>,
+}
+
+impl Stanza {
+    /// The first value of the given tag.
+    pub fn get(&self, tag: &str) -> Option<&str> {
+        self.tags.iter()
+            .find(|(name, _)| name == tag)
+            .map(|(_, value)| &**value)
+    }
+
+    /// Every value of the given tag, e.g. each `is_a` of a term.
+    pub fn get_all<'a>(&'a self, tag: &'a str) -> impl Iterator<Item=&'a str> + 'a {
+        self.tags.iter()
+            .filter(move |(name, _)| name == tag)
+            .map(|(_, value)| &**value)
+    }
+}
+
+/// Parses the stanzas of an OBO 1.2/1.4 file.
+///
+/// The header (everything before the first stanza) is skipped. Trailing
+/// `! comments` and `{qualifiers}` are removed from values, except from
+/// quoted values such as definitions.
+pub(crate) fn parse_stanzas<R: BufRead>(reader: R) -> Result<Vec<Stanza>, Error> {
+    let mut stanzas = Vec::new();
+    let mut current: Option<Stanza> = None;
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('!') {
+            continue;
+        }
+
+        if trimmed.starts_with('[') {
+            let kind = trimmed.strip_prefix('[').and_then(|kind| kind.strip_suffix(']'))
+                .ok_or_else(|| ParseError {
+                    line: i as u64 + 1,
+                    column: None,
+                    row: line.to_string(),
+                    cause: ParseErrorKind::InvalidValue("unterminated stanza name".to_string()),
+                })?;
+            stanzas.extend(current.take());
+            current = Some(Stanza { kind: kind.to_string(), tags: Vec::new() });
+            continue;
+        }
+
+        // Tags before the first stanza belong to the header
+        let stanza = match &mut current {
+            Some(stanza) => stanza,
+            None => continue,
+        };
+
+        let (tag, value) = trimmed.split_once(':')
+            .filter(|(tag, _)| !tag.contains(char::is_whitespace))
+            .ok_or_else(|| ParseError {
+                line: i as u64 + 1,
+                column: None,
+                row: line.to_string(),
+                cause: ParseErrorKind::InvalidValue("expected a 'tag: value' pair".to_string()),
+            })?;
+        stanza.tags.push((tag.to_string(), clean_value(value.trim()).to_string()));
+    }
+    stanzas.extend(current);
+    Ok(stanzas)
+}
+
+/// Removes any trailing comment and qualifier block from a tag value.
+fn clean_value(value: &str) -> &str {
+    if value.starts_with('"') {
+        return value;
+    }
+    let value = value.split(" !").next().unwrap_or_default();
+    let value = value.split(" {").next().unwrap_or_default();
+    value.trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_stanzas() {
+        let obo = r#"format-version: 1.2
+ontology: eco
+
+[Term]
+id: ECO:0000314
+name: direct assay evidence used in manual assertion
+def: "A type of direct assay evidence that is used in a manual assertion!" []
+is_a: ECO:0000002 {source="ECO"} ! direct assay evidence
+is_a: ECO:0000269 ! experimental evidence used in manual assertion
+
+[Typedef]
+id: used_in
+"#;
+        let stanzas = parse_stanzas(Cursor::new(obo)).unwrap();
+        assert_eq!(stanzas.len(), 2);
+        assert_eq!(stanzas[0].kind, "Term");
+        assert_eq!(stanzas[0].get("id"), Some("ECO:0000314"));
+        assert_eq!(stanzas[0].get("def"), Some(r#""A type of direct assay evidence that is used in a manual assertion!" []"#));
+        assert_eq!(stanzas[0].get_all("is_a").collect::<Vec<_>>(), vec!["ECO:0000002", "ECO:0000269"]);
+        assert_eq!(stanzas[1].kind, "Typedef");
+
+        let error = parse_stanzas(Cursor::new("[Term]\nid ECO:0000314\n")).unwrap_err();
+        assert!(matches!(error, Error::Parse(ParseError { line: 2, .. })));
+    }
+}
@@ -0,0 +1,464 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{BufRead, Read};
+use serde::Deserialize;
+use crate::Aspect;
+use crate::error::{Error, ParseError, ParseErrorKind};
+use crate::obo;
+
+/// A term of an ontology such as GO.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Term {
+    pub id: String,
+    pub name: String,
+    /// The namespace of the term, e.g. `molecular_function`.
+    pub namespace: String,
+    pub obsolete: bool,
+    /// The term which replaces this one, if it is obsolete.
+    pub replaced_by: Option<String>,
+    /// Terms which may be appropriate substitutes for this one, if it is
+    /// obsolete.
+    pub consider: Vec<String>,
+    /// Secondary IDs which refer to this term, e.g. from merged terms.
+    pub alt_ids: Vec<String>,
+    pub is_a: Vec<String>,
+    pub part_of: Vec<String>,
+    /// The subsets (such as GO slims) which include this term.
+    pub subsets: Vec<String>,
+}
+
+impl Term {
+    /// The GO Aspect corresponding to this term's namespace, if any.
+    pub fn aspect(&self) -> Option<Aspect> {
+        match &*self.namespace {
+            "molecular_function" => Some(Aspect::MolecularFunction),
+            "biological_process" => Some(Aspect::BiologicalProcess),
+            "cellular_component" => Some(Aspect::CellularComponent),
+            _ => None,
+        }
+    }
+
+    /// The terms this term is directly related to by `is_a` or `part_of`.
+    pub fn parents(&self) -> impl Iterator<Item=&str> {
+        self.is_a.iter().chain(&self.part_of).map(String::as_str)
+    }
+}
+
+/// A graph of ontology terms, such as the Gene Ontology.
+///
+/// Terms are related by their `is_a` and `part_of` edges, which are the
+/// relations along which GO annotations may be propagated.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Ontology {
+    terms: Vec<Term>,
+    by_id: HashMap<String, usize>,
+    alt_ids: HashMap<String, usize>,
+    children: HashMap<String, Vec<usize>>,
+}
+
+impl Ontology {
+    pub fn from_terms(terms: Vec<Term>) -> Ontology {
+        let mut by_id = HashMap::new();
+        let mut alt_ids = HashMap::new();
+        let mut children: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, term) in terms.iter().enumerate() {
+            by_id.insert(term.id.to_string(), i);
+            for alt_id in &term.alt_ids {
+                alt_ids.insert(alt_id.to_string(), i);
+            }
+            for parent in term.parents() {
+                children.entry(parent.to_string()).or_default().push(i);
+            }
+        }
+        Ontology { terms, by_id, alt_ids, children }
+    }
+
+    /// Reads an ontology in either OBO or OBO-Graphs JSON format, telling
+    /// them apart by the first character of the input.
+    pub fn parse_from<R: BufRead>(mut reader: R) -> Result<Ontology, Error> {
+        let is_json = reader.fill_buf()?.iter()
+            .find(|byte| !byte.is_ascii_whitespace()) == Some(&b'{');
+        if is_json {
+            Ontology::parse_obograph_json(reader)
+        } else {
+            Ontology::parse_obo(reader)
+        }
+    }
+
+    /// Reads the `[Term]` stanzas of an OBO file, such as `go-basic.obo`.
+    pub fn parse_obo<R: BufRead>(reader: R) -> Result<Ontology, Error> {
+        let terms = obo::parse_stanzas(reader)?.into_iter()
+            .filter(|stanza| stanza.kind == "Term")
+            .filter_map(|stanza| {
+                let strings = |tag| stanza.get_all(tag).map(str::to_string).collect();
+                let part_of = stanza.get_all("relationship")
+                    .filter_map(|relationship| relationship.strip_prefix("part_of "))
+                    .map(|parent| parent.trim().to_string())
+                    .collect();
+
+                Some(Term {
+                    id: stanza.get("id")?.to_string(),
+                    name: stanza.get("name").unwrap_or_default().to_string(),
+                    namespace: stanza.get("namespace").unwrap_or_default().to_string(),
+                    obsolete: stanza.get("is_obsolete") == Some("true"),
+                    replaced_by: stanza.get("replaced_by").map(str::to_string),
+                    consider: strings("consider"),
+                    alt_ids: strings("alt_id"),
+                    is_a: strings("is_a"),
+                    part_of,
+                    subsets: strings("subset"),
+                })
+            })
+            .collect();
+        Ok(Ontology::from_terms(terms))
+    }
+
+    /// Reads the classes and edges of an OBO-Graphs JSON file, such as
+    /// `go-basic.json`.
+    pub fn parse_obograph_json<R: Read>(reader: R) -> Result<Ontology, Error> {
+        let document: obograph::Document = serde_json::from_reader(reader)
+            .map_err(|e| ParseError {
+                line: e.line() as u64,
+                column: None,
+                row: String::new(),
+                cause: ParseErrorKind::InvalidValue(e.to_string()),
+            })?;
+        Ok(Ontology::from_terms(obograph::terms(document)))
+    }
+
+    /// Finds a term by its primary ID.
+    pub fn term(&self, id: &str) -> Option<&Term> {
+        self.by_id.get(id).map(|&i| &self.terms[i])
+    }
+
+    /// Finds a term by its primary ID or any of its alternative IDs.
+    pub fn resolve(&self, id: &str) -> Option<&Term> {
+        self.by_id.get(id).or_else(|| self.alt_ids.get(id)).map(|&i| &self.terms[i])
+    }
+
+    /// The ID which should be used in place of the given one: the primary ID
+    /// of the term it refers to, or of the term which replaces it if it is
+    /// obsolete. Obsolete terms without a replacement keep their own ID.
+    pub fn primary_id(&self, id: &str) -> Option<&str> {
+        let mut term = self.resolve(id)?;
+        let mut seen = HashSet::new();
+        while let Some(replacement) = term.replaced_by.as_ref().filter(|_| term.obsolete) {
+            match self.resolve(replacement) {
+                Some(replacement) if seen.insert(&*term.id) => term = replacement,
+                _ => break,
+            }
+        }
+        Some(&term.id)
+    }
+
+    /// The name of a term, e.g. `nucleus` for `GO:0005634`.
+    pub fn name(&self, id: &str) -> Option<&str> {
+        self.resolve(id).map(|term| &*term.name)
+    }
+
+    pub fn terms(&self) -> impl Iterator<Item=&Term> {
+        self.terms.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// The terms which are directly `is_a` or `part_of` a term.
+    pub fn children(&self, id: &str) -> impl Iterator<Item=&Term> {
+        self.children.get(id).into_iter().flatten().map(move |&i| &self.terms[i])
+    }
+
+    /// Every term which a term is transitively `is_a` or `part_of`, nearest
+    /// first. The term itself is not included.
+    pub fn ancestors(&self, id: &str) -> Vec<&str> {
+        let term = match self.resolve(id) {
+            Some(term) => term,
+            None => return Vec::new(),
+        };
+
+        let mut ancestors = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<&str> = term.parents().collect();
+        while let Some(ancestor) = queue.pop_front() {
+            if !seen.insert(ancestor) { continue; }
+            ancestors.push(ancestor);
+            queue.extend(self.term(ancestor).into_iter().flat_map(Term::parents));
+        }
+        ancestors
+    }
+
+    /// Every term which is transitively `is_a` or `part_of` a term, nearest
+    /// first. The term itself is not included.
+    pub fn descendants(&self, id: &str) -> Vec<&str> {
+        let term = match self.resolve(id) {
+            Some(term) => term,
+            None => return Vec::new(),
+        };
+
+        let mut descendants = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<&Term> = self.children(&term.id).collect();
+        while let Some(descendant) = queue.pop_front() {
+            if !seen.insert(&*descendant.id) { continue; }
+            descendants.push(&*descendant.id);
+            queue.extend(self.children(&descendant.id));
+        }
+        descendants
+    }
+
+    /// Whether `id` is `ancestor` or one of its descendants.
+    pub fn is_a(&self, id: &str, ancestor: &str) -> bool {
+        let ancestor = self.resolve(ancestor).map_or(ancestor, |term| &term.id);
+        self.resolve(id).map_or(id, |term| &term.id) == ancestor
+            || self.ancestors(id).contains(&ancestor)
+    }
+}
+
+/// The parts of the OBO-Graphs JSON format which describe terms.
+mod obograph {
+    use super::*;
+
+    const IRI_PREFIX: &str = "http://purl.obolibrary.org/obo/";
+    const HAS_NAMESPACE: &str = "http://www.geneontology.org/formats/oboInOwl#hasOBONamespace";
+    const HAS_ALT_ID: &str = "http://www.geneontology.org/formats/oboInOwl#hasAlternativeId";
+    const CONSIDER: &str = "http://www.geneontology.org/formats/oboInOwl#consider";
+    const REPLACED_BY: &str = "http://purl.obolibrary.org/obo/IAO_0100001";
+    const PART_OF: &str = "http://purl.obolibrary.org/obo/BFO_0000050";
+
+    #[derive(Deserialize)]
+    pub struct Document {
+        graphs: Vec<Graph>,
+    }
+
+    #[derive(Deserialize)]
+    struct Graph {
+        #[serde(default)]
+        nodes: Vec<Node>,
+        #[serde(default)]
+        edges: Vec<Edge>,
+    }
+
+    #[derive(Deserialize)]
+    struct Node {
+        id: String,
+        lbl: Option<String>,
+        #[serde(rename = "type")]
+        node_type: Option<String>,
+        #[serde(default)]
+        meta: Meta,
+    }
+
+    #[derive(Default, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Meta {
+        #[serde(default)]
+        deprecated: bool,
+        #[serde(default)]
+        subsets: Vec<String>,
+        #[serde(default)]
+        basic_property_values: Vec<PropertyValue>,
+    }
+
+    #[derive(Deserialize)]
+    struct PropertyValue {
+        pred: String,
+        val: String,
+    }
+
+    #[derive(Deserialize)]
+    struct Edge {
+        sub: String,
+        pred: String,
+        obj: String,
+    }
+
+    /// Converts an OBO PURL (e.g. `http://purl.obolibrary.org/obo/GO_0005634`)
+    /// into a CURIE (e.g. `GO:0005634`).
+    fn curie(iri: &str) -> String {
+        match iri.strip_prefix(IRI_PREFIX) {
+            Some(id) => id.replacen('_', ":", 1),
+            None => iri.to_string(),
+        }
+    }
+
+    pub fn terms(document: Document) -> Vec<Term> {
+        let mut terms = Vec::new();
+        let mut by_id = HashMap::new();
+        let mut edges = Vec::new();
+        for graph in document.graphs {
+            for node in graph.nodes {
+                if node.node_type.as_deref() != Some("CLASS") { continue; }
+
+                let mut term = Term {
+                    id: curie(&node.id),
+                    name: node.lbl.unwrap_or_default(),
+                    obsolete: node.meta.deprecated,
+                    subsets: node.meta.subsets.iter()
+                        .map(|subset| subset.rsplit('#').next().unwrap_or_default().to_string())
+                        .collect(),
+                    ..Term::default()
+                };
+                for property in node.meta.basic_property_values {
+                    match &*property.pred {
+                        HAS_NAMESPACE => term.namespace = property.val,
+                        HAS_ALT_ID => term.alt_ids.push(property.val),
+                        CONSIDER => term.consider.push(curie(&property.val)),
+                        REPLACED_BY => term.replaced_by = Some(curie(&property.val)),
+                        _ => (),
+                    }
+                }
+                by_id.insert(term.id.to_string(), terms.len());
+                terms.push(term);
+            }
+            edges.extend(graph.edges);
+        }
+
+        for edge in edges {
+            let term = match by_id.get(&curie(&edge.sub)) {
+                Some(&i) => &mut terms[i],
+                None => continue,
+            };
+            match &*edge.pred {
+                "is_a" => term.is_a.push(curie(&edge.obj)),
+                PART_OF => term.part_of.push(curie(&edge.obj)),
+                _ => (),
+            }
+        }
+        terms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const GO_OBO: &str = r#"format-version: 1.2
+data-version: releases/2020-01-01
+subsetdef: goslim_generic "Generic GO slim"
+
+[Term]
+id: GO:0005575
+name: cellular_component
+namespace: cellular_component
+subset: goslim_generic
+
+[Term]
+id: GO:0043226
+name: organelle
+namespace: cellular_component
+is_a: GO:0005575 ! cellular_component
+subset: goslim_generic
+
+[Term]
+id: GO:0005634
+name: nucleus
+namespace: cellular_component
+alt_id: GO:0005633
+is_a: GO:0043226 ! organelle
+subset: goslim_generic
+
+[Term]
+id: GO:0005730
+name: nucleolus
+namespace: cellular_component
+is_a: GO:0043226 ! organelle
+relationship: part_of GO:0005634 ! nucleus
+
+[Term]
+id: GO:0005635
+name: obsolete nuclear envelope
+namespace: cellular_component
+is_obsolete: true
+replaced_by: GO:0005634
+
+[Typedef]
+id: part_of
+name: part of
+"#;
+
+    #[test]
+    fn test_parse_obo() {
+        let ontology = Ontology::parse_from(Cursor::new(GO_OBO)).unwrap();
+        assert_eq!(ontology.len(), 5);
+
+        let nucleolus = ontology.term("GO:0005730").unwrap();
+        assert_eq!(nucleolus.name, "nucleolus");
+        assert_eq!(nucleolus.aspect(), Some(Aspect::CellularComponent));
+        assert_eq!(nucleolus.is_a, vec!["GO:0043226"]);
+        assert_eq!(nucleolus.part_of, vec!["GO:0005634"]);
+
+        assert_eq!(ontology.resolve("GO:0005633").map(|term| &*term.id), Some("GO:0005634"));
+        assert_eq!(ontology.name("GO:0005633"), Some("nucleus"));
+        assert_eq!(ontology.term("GO:0005633"), None);
+
+        let obsolete = ontology.term("GO:0005635").unwrap();
+        assert!(obsolete.obsolete);
+        assert_eq!(obsolete.replaced_by.as_deref(), Some("GO:0005634"));
+        assert_eq!(ontology.primary_id("GO:0005635"), Some("GO:0005634"));
+        assert_eq!(ontology.primary_id("GO:0005633"), Some("GO:0005634"));
+        assert_eq!(ontology.primary_id("GO:0005730"), Some("GO:0005730"));
+        assert_eq!(ontology.term("GO:0005634").unwrap().subsets, vec!["goslim_generic"]);
+    }
+
+    #[test]
+    fn test_ancestors_and_descendants() {
+        let ontology = Ontology::parse_obo(Cursor::new(GO_OBO)).unwrap();
+        assert_eq!(ontology.ancestors("GO:0005730"), vec!["GO:0043226", "GO:0005634", "GO:0005575"]);
+        assert_eq!(ontology.descendants("GO:0043226"), vec!["GO:0005634", "GO:0005730"]);
+        assert!(ontology.is_a("GO:0005730", "GO:0005633"));
+        assert!(ontology.is_a("GO:0005634", "GO:0005634"));
+        assert!(!ontology.is_a("GO:0005634", "GO:0005730"));
+    }
+
+    #[test]
+    fn test_parse_obograph_json() {
+        let json = r#"{
+  "graphs": [{
+    "nodes": [
+      { "id": "http://purl.obolibrary.org/obo/GO_0043226", "lbl": "organelle", "type": "CLASS",
+        "meta": { "basicPropertyValues": [
+          { "pred": "http://www.geneontology.org/formats/oboInOwl#hasOBONamespace", "val": "cellular_component" }
+        ] } },
+      { "id": "http://purl.obolibrary.org/obo/GO_0005634", "lbl": "nucleus", "type": "CLASS",
+        "meta": {
+          "subsets": ["http://purl.obolibrary.org/obo/go#goslim_generic"],
+          "basicPropertyValues": [
+            { "pred": "http://www.geneontology.org/formats/oboInOwl#hasOBONamespace", "val": "cellular_component" },
+            { "pred": "http://www.geneontology.org/formats/oboInOwl#hasAlternativeId", "val": "GO:0005633" }
+          ] } },
+      { "id": "http://purl.obolibrary.org/obo/GO_0005730", "lbl": "nucleolus", "type": "CLASS" },
+      { "id": "http://purl.obolibrary.org/obo/GO_0005635", "lbl": "obsolete nuclear envelope", "type": "CLASS",
+        "meta": { "deprecated": true, "basicPropertyValues": [
+          { "pred": "http://purl.obolibrary.org/obo/IAO_0100001", "val": "http://purl.obolibrary.org/obo/GO_0005634" }
+        ] } },
+      { "id": "http://purl.obolibrary.org/obo/BFO_0000050", "lbl": "part of", "type": "PROPERTY" }
+    ],
+    "edges": [
+      { "sub": "http://purl.obolibrary.org/obo/GO_0005634", "pred": "is_a", "obj": "http://purl.obolibrary.org/obo/GO_0043226" },
+      { "sub": "http://purl.obolibrary.org/obo/GO_0005730", "pred": "is_a", "obj": "http://purl.obolibrary.org/obo/GO_0043226" },
+      { "sub": "http://purl.obolibrary.org/obo/GO_0005730", "pred": "http://purl.obolibrary.org/obo/BFO_0000050", "obj": "http://purl.obolibrary.org/obo/GO_0005634" }
+    ]
+  }]
+}"#;
+        let ontology = Ontology::parse_from(Cursor::new(json)).unwrap();
+        assert_eq!(ontology.len(), 4);
+
+        let nucleus = ontology.resolve("GO:0005633").unwrap();
+        assert_eq!(nucleus.id, "GO:0005634");
+        assert_eq!(nucleus.aspect(), Some(Aspect::CellularComponent));
+        assert_eq!(nucleus.subsets, vec!["goslim_generic"]);
+
+        let nucleolus = ontology.term("GO:0005730").unwrap();
+        assert_eq!(nucleolus.part_of, vec!["GO:0005634"]);
+        assert_eq!(ontology.ancestors("GO:0005730"), vec!["GO:0043226", "GO:0005634"]);
+
+        let obsolete = ontology.term("GO:0005635").unwrap();
+        assert!(obsolete.obsolete);
+        assert_eq!(obsolete.replaced_by.as_deref(), Some("GO:0005634"));
+    }
+}
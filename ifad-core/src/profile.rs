@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::io::Write;
+use crate::{Annotation, AnnotationStatus, Aspect, Gene, Index, StatusCombination};
+
+/// The classification of one Gene, along with the annotations behind it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneProfile<'a, 'b> {
+    pub gene: &'a Gene<'a>,
+    /// The status of the Gene for each Aspect, as given by
+    /// `Index::status_combination`.
+    pub statuses: StatusCombination,
+    /// The annotations of the Gene for each Aspect, ordered by GO term.
+    pub annotations: HashMap<Aspect, Vec<&'b Annotation<'b>>>,
+}
+
+impl<'a, 'b> Index<'a, 'b> {
+    /// Finds a Gene by its ID or any of its aliases, or None if no Gene or
+    /// more than one Gene goes by the name (see `AliasIndex`).
+    pub fn find_gene(&self, name: &str) -> Option<&'a Gene<'a>> {
+        let gene_id = self.aliases.gene_id(name)?;
+        self.anno_index.get(gene_id).map(|&(gene, _)| gene)
+    }
+
+    /// The status of a Gene for each Aspect and the annotations supporting
+    /// them, or None if no Gene goes by the name (see `find_gene`).
+    pub fn gene_profile(&self, name: &str) -> Option<GeneProfile<'a, 'b>> {
+        let gene = self.find_gene(name)?;
+        let (_, gene_annotations) = &self.anno_index[gene.gene_id];
+
+        let mut annotations: HashMap<Aspect, Vec<&'b Annotation<'b>>> = HashMap::new();
+        for &annotation in gene_annotations {
+            annotations.entry(annotation.aspect).or_default().push(annotation);
+        }
+        for aspect_annotations in annotations.values_mut() {
+            aspect_annotations.sort_by_key(|annotation| (annotation.go_term, annotation.evidence_code, annotation.reference));
+        }
+
+        Some(GeneProfile { gene, statuses: self.status_combination(gene), annotations })
+    }
+}
+
+impl<'b> GeneProfile<'_, 'b> {
+    pub fn status(&self, aspect: Aspect) -> AnnotationStatus {
+        self.statuses.status(aspect)
+    }
+
+    /// The annotations of the Gene for an Aspect.
+    pub fn annotations(&self, aspect: Aspect) -> &[&'b Annotation<'b>] {
+        self.annotations.get(&aspect).map_or(&[], |annotations| annotations)
+    }
+
+    /// Writes the status of the Gene for each Aspect, followed by its
+    /// annotations for that Aspect.
+    pub fn write_text<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, "{} ({})", self.gene.gene_id, self.gene.gene_product_type)?;
+        for &aspect in Aspect::ALL {
+            let annotations = self.annotations(aspect);
+            writeln!(writer, "{}  {:<11}  {} annotations", aspect.as_str(), self.status(aspect).as_str(), annotations.len())?;
+            for annotation in annotations {
+                let not = if annotation.invert { "NOT " } else { "" };
+                writeln!(writer, "    {}{}  {}  {}  {}  {}",
+                         not,
+                         annotation.go_term,
+                         annotation.evidence_code,
+                         annotation.reference,
+                         annotation.assigned_by,
+                         annotation.date)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnnotationRecord, EvidencePolicy, GeneRecord};
+
+    fn record(gene: &str, synonyms: &str, aspect: Aspect, go_term: &str, evidence_code: &str) -> AnnotationRecord {
+        AnnotationRecord {
+            db: "TAIR".to_string(),
+            database_id: "locus:2031476".to_string(),
+            db_object_symbol: "".to_string(),
+            invert: "".to_string(),
+            go_term: go_term.to_string(),
+            reference: "TAIR:Communication:1345790".to_string(),
+            evidence_code: evidence_code.to_string(),
+            additional_evidence: "".to_string(),
+            aspect,
+            unique_gene_name: gene.to_string(),
+            alternative_gene_name: synonyms.to_string(),
+            gene_product_type: "protein".to_string(),
+            taxon: "taxon:3702".to_string(),
+            date: "20190907".to_string(),
+            assigned_by: "TAIR".to_string(),
+            annotation_extension: "".to_string(),
+            gene_product_form_id: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_gene_profile() {
+        let gene_records: Vec<GeneRecord> = ["AT1G74030", "AT2G36530"].iter()
+            .map(|gene_id| GeneRecord {
+                gene_id: gene_id.to_string(),
+                gene_product_type: "protein".to_string(),
+            })
+            .collect();
+        let genes: Vec<Gene> = gene_records.iter().map(Gene::from_record).collect();
+        let anno_records = vec![
+            record("AT1G74030", "ENO1|F2P24.12", Aspect::CellularComponent, "GO:0009507", "IEA"),
+            record("AT1G74030", "ENO1|F2P24.12", Aspect::CellularComponent, "GO:0005737", "IDA"),
+            record("AT1G74030", "ENO1|F2P24.12", Aspect::MolecularFunction, "GO:0004634", "ISS"),
+            record("AT2G36530", "ENO2|LOS2", Aspect::MolecularFunction, "GO:0004634", "IDA"),
+        ];
+        let annotations: Vec<Annotation> = anno_records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .collect();
+        let index = Index::new(&genes, &annotations);
+
+        let profile = index.gene_profile("ENO1").unwrap();
+        assert_eq!(profile.gene.gene_id, "AT1G74030");
+        assert_eq!(profile, index.gene_profile("AT1G74030").unwrap());
+        assert_eq!(profile.status(Aspect::MolecularFunction), AnnotationStatus::KnownOther);
+        assert_eq!(profile.status(Aspect::BiologicalProcess), AnnotationStatus::Unannotated);
+        assert_eq!(profile.status(Aspect::CellularComponent), AnnotationStatus::KnownExperimental);
+        let cc_terms: Vec<&str> = profile.annotations(Aspect::CellularComponent).iter()
+            .map(|annotation| annotation.go_term)
+            .collect();
+        assert_eq!(cc_terms, vec!["GO:0005737", "GO:0009507"]);
+        assert!(profile.annotations(Aspect::BiologicalProcess).is_empty());
+
+        assert_eq!(index.gene_profile("LOS2").unwrap().gene.gene_id, "AT2G36530");
+        assert!(index.gene_profile("ENO3").is_none());
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+use crate::{Annotation, AnnotationStatus, Gene, Index, Ontology, QueryResult, Segment};
+use crate::index::{classify, GeneIndex};
+
+/// An annotation in an Index, paired with the Gene it annotates.
+pub(crate) type Hit<'a> = (&'a Gene<'a>, &'a Annotation<'a>);
+
+/// An index of the annotations in an Index by GO term, following the true
+/// path rule: an annotation to a term is also an annotation to each of the
+/// terms it is transitively `is_a` or `part_of`.
+///
+/// Propagated annotations keep the Aspect and AnnotationStatus they have in
+/// the Index, so the Genes annotated to a term (or its descendants) can be
+/// classified just as the Index classifies all of them.
+#[derive(Debug)]
+pub struct TermIndex<'a, 'o> {
+    index: &'a Index<'a, 'a>,
+    ontology: &'o Ontology,
+    /// The annotations made directly to each term.
+    direct: HashMap<String, Vec<Hit<'a>>>,
+    /// The annotations made to each term or any of its descendants.
+    propagated: HashMap<String, Vec<Hit<'a>>>,
+}
+
+impl<'a, 'o> TermIndex<'a, 'o> {
+    /// Indexes the annotations of an Index by their GO terms, propagating
+    /// them to every ancestor of those terms.
+    ///
+    /// Annotations to alternative IDs are indexed under the primary ID of
+    /// their term, and annotations to terms which are not in the ontology
+    /// are indexed under their own term only.
+    pub fn new(index: &'a Index, ontology: &'o Ontology) -> TermIndex<'a, 'o> {
+        let mut direct: HashMap<String, Vec<Hit>> = HashMap::new();
+        let mut propagated: HashMap<String, Vec<Hit>> = HashMap::new();
+        let mut ancestors: HashMap<&str, Vec<&str>> = HashMap::new();
+
+        for (gene, annotations) in index.anno_index.values() {
+            for &annotation in annotations {
+                let hit = (*gene, annotation);
+                let term = ontology.resolve(annotation.go_term)
+                    .map_or(annotation.go_term, |term| &term.id);
+                direct.entry(term.to_string()).or_default().push(hit);
+                propagated.entry(term.to_string()).or_default().push(hit);
+
+                let term_ancestors = ancestors.entry(term)
+                    .or_insert_with(|| ontology.ancestors(term));
+                for ancestor in term_ancestors.iter() {
+                    propagated.entry(ancestor.to_string()).or_default().push(hit);
+                }
+            }
+        }
+
+        TermIndex { index, ontology, direct, propagated }
+    }
+
+    pub fn index(&self) -> &'a Index<'a, 'a> {
+        self.index
+    }
+
+    pub fn ontology(&self) -> &'o Ontology {
+        self.ontology
+    }
+
+    /// Every term with annotations to it or to one of its descendants.
+    pub fn terms(&self) -> impl Iterator<Item=&str> {
+        self.propagated.keys().map(String::as_str)
+    }
+
+    /// The annotations to a term, and to its descendants if asked.
+    fn hits(&self, term: &str, include_descendants: bool) -> &[Hit<'a>] {
+        let term = self.ontology.resolve(term).map_or(term, |term| &term.id);
+        let by_term = if include_descendants { &self.propagated } else { &self.direct };
+        by_term.get(term).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Every annotation to a term, and to its descendants if asked,
+    /// whether or not it is used for classification.
+    pub fn annotations(&self, term: &str, include_descendants: bool) -> impl Iterator<Item=&'a Annotation<'a>> + '_ {
+        self.hits(term, include_descendants).iter().map(|&(_, annotation)| annotation)
+    }
+
+    /// Classifies the Genes annotated to a term (and to its descendants if
+    /// asked) by the Aspect and AnnotationStatus of those annotations, using
+    /// the options of the Index.
+    ///
+    /// Unlike an Index, Genes are never Unannotated for a term.
+    pub fn gene_index(&self, term: &str, include_descendants: bool) -> GeneIndex<'a> {
+        classify(self.hits(term, include_descendants).iter().copied(), &self.index.options)
+    }
+
+    /// Finds the Genes which are annotated to a term (or to one of its
+    /// descendants if asked), along with those annotations.
+    ///
+    /// Annotations which are not used for classification, such as negated
+    /// or excluded ones, do not count, nor do NOT annotations which are
+    /// classified as Negated.
+    pub fn query(&self, term: &str, include_descendants: bool) -> QueryResult<'a> {
+        let mut queried_genes = HashSet::new();
+        let mut queried_annotations = HashSet::new();
+        for &(gene, annotation) in self.annotated_hits(term, include_descendants) {
+            queried_genes.insert(gene);
+            queried_annotations.insert(annotation);
+        }
+        QueryResult::new(self.index, queried_genes, queried_annotations)
+    }
+
+    /// The Genes which are annotated to a term (or to one of its descendants
+    /// if asked), counting annotations just as `query` does.
+    pub fn genes(&self, term: &str, include_descendants: bool) -> HashSet<&'a Gene<'a>> {
+        self.annotated_hits(term, include_descendants).map(|&(gene, _)| gene).collect()
+    }
+
+    /// The annotations to a term which annotate their Gene with it.
+    fn annotated_hits(&self, term: &str, include_descendants: bool) -> impl Iterator<Item=&Hit<'a>> {
+        self.hits(term, include_descendants).iter()
+            .filter(move |(_, annotation)| match self.index.status_of(annotation) {
+                None | Some(AnnotationStatus::Negated) => false,
+                Some(_) => true,
+            })
+    }
+
+    /// Finds the Genes which belong to a segment when only considering their
+    /// annotations to a term (or to its descendants if asked), along with
+    /// the annotations which placed them there.
+    pub fn query_segment(&self, term: &str, include_descendants: bool, segment: Segment) -> QueryResult<'a> {
+        let gene_index = self.gene_index(term, include_descendants);
+        let queried_genes: HashSet<&Gene> = gene_index.get(&segment.aspect())
+            .and_then(|by_status| by_status.get(&segment.annotation_status()))
+            .cloned()
+            .unwrap_or_default();
+
+        let queried_annotations = self.hits(term, include_descendants).iter()
+            .filter(|(gene, annotation)| queried_genes.contains(gene)
+                && annotation.aspect == segment.aspect()
+                && self.index.status_of(annotation) == Some(segment.annotation_status()))
+            .map(|&(_, annotation)| annotation)
+            .collect();
+
+        QueryResult::new(self.index, queried_genes, queried_annotations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::{AnnotationRecord, Aspect, EvidencePolicy, GeneRecord};
+
+    const GO_OBO: &str = "[Term]
+id: GO:0005575
+name: cellular_component
+namespace: cellular_component
+
+[Term]
+id: GO:0043226
+name: organelle
+namespace: cellular_component
+is_a: GO:0005575 ! cellular_component
+
+[Term]
+id: GO:0005634
+name: nucleus
+namespace: cellular_component
+alt_id: GO:0005633
+is_a: GO:0043226 ! organelle
+
+[Term]
+id: GO:0005730
+name: nucleolus
+namespace: cellular_component
+is_a: GO:0043226 ! organelle
+relationship: part_of GO:0005634 ! nucleus
+";
+
+    fn record(gene: &str, go_term: &str, evidence_code: &str) -> AnnotationRecord {
+        AnnotationRecord {
+            db: "TAIR".to_string(),
+            database_id: "locus:2031476".to_string(),
+            db_object_symbol: "".to_string(),
+            invert: "".to_string(),
+            go_term: go_term.to_string(),
+            reference: "TAIR:Communication:1345790".to_string(),
+            evidence_code: evidence_code.to_string(),
+            additional_evidence: "".to_string(),
+            aspect: Aspect::CellularComponent,
+            unique_gene_name: gene.to_string(),
+            alternative_gene_name: "".to_string(),
+            gene_product_type: "protein".to_string(),
+            taxon: "taxon:3702".to_string(),
+            date: "20190907".to_string(),
+            assigned_by: "TAIR".to_string(),
+            annotation_extension: "".to_string(),
+            gene_product_form_id: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_propagate_annotations() {
+        let ontology = Ontology::parse_from(Cursor::new(GO_OBO)).unwrap();
+        let gene_records: Vec<GeneRecord> = ["AT1G01010", "AT1G01020", "AT1G01030"].iter()
+            .map(|gene_id| GeneRecord {
+                gene_id: gene_id.to_string(),
+                gene_product_type: "protein".to_string(),
+            })
+            .collect();
+        let genes: Vec<Gene> = gene_records.iter().map(Gene::from_record).collect();
+
+        let anno_records = vec![
+            record("AT1G01010", "GO:0005730", "IDA"),
+            record("AT1G01010", "GO:0005634", "IEA"),
+            record("AT1G01020", "GO:0005633", "IEA"),
+            record("AT1G01030", "GO:0005575", "ND"),
+        ];
+        let annotations: Vec<Annotation> = anno_records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .collect();
+        let index = Index::new(&genes, &annotations);
+        let terms = TermIndex::new(&index, &ontology);
+
+        // The nucleolus is part of the nucleus, which is an organelle
+        assert_eq!(terms.annotations("GO:0005634", false).count(), 2);
+        assert_eq!(terms.annotations("GO:0005634", true).count(), 3);
+        assert_eq!(terms.annotations("GO:0043226", false).count(), 0);
+        assert_eq!(terms.annotations("GO:0043226", true).count(), 3);
+        assert_eq!(terms.annotations("GO:0005575", true).count(), 4);
+
+        // Genes keep their classification from the annotations to the term
+        let nucleus = terms.gene_index("GO:0005633", true);
+        let cc = &nucleus[&Aspect::CellularComponent];
+        assert_eq!(cc[&AnnotationStatus::KnownExperimental], [&genes[0]].iter().copied().collect());
+        assert_eq!(cc[&AnnotationStatus::KnownOther], [&genes[1]].iter().copied().collect());
+        assert!(!cc.contains_key(&AnnotationStatus::Unknown));
+
+        let result = terms.query("GO:0043226", true);
+        let mut queried: Vec<&str> = result.genes_iter().map(|gene| gene.gene_id).collect();
+        queried.sort();
+        assert_eq!(queried, vec!["AT1G01010", "AT1G01020"]);
+
+        let segment = Segment::new(Aspect::CellularComponent, AnnotationStatus::KnownExperimental);
+        let result = terms.query_segment("GO:0005634", true, segment);
+        assert_eq!(result.genes_iter().map(|gene| gene.gene_id).collect::<Vec<_>>(), vec!["AT1G01010"]);
+        assert_eq!(result.annotations_iter().map(|anno| anno.go_term).collect::<Vec<_>>(), vec!["GO:0005730"]);
+        assert_eq!(terms.query_segment("GO:0005634", false, segment).genes_iter().count(), 0);
+    }
+}
@@ -1,13 +1,12 @@
-use crate::{Aspect, AnnotationStatus, Index, Gene, Annotation};
+use crate::{Aspect, AnnotationStatus, Index, Gene, Annotation, Ontology, TermIndex};
 use std::collections::HashSet;
 use std::ops::Deref;
 use std::convert::TryFrom;
 
 #[derive(Debug)]
 pub struct QueryResult<'a> {
+    index: &'a Index<'a, 'a>,
     ordered: bool,
-    genes: &'a [Gene<'a>],
-    annotations: &'a [Annotation<'a>],
     queried_genes: HashSet<&'a Gene<'a>>,
     queried_annotations: HashSet<&'a Annotation<'a>>,
 }
@@ -34,26 +33,39 @@ impl<A, B, T> Iterator for EitherIter<A, B>
 impl QueryResult<'_> {
     pub fn empty<'a>(index: &'a Index) -> QueryResult<'a> {
         QueryResult {
+            index,
             ordered: false,
-            genes: &index.genes,
-            annotations: &index.annotations,
             queried_genes: HashSet::new(),
             queried_annotations: HashSet::new(),
         }
     }
 
-    pub fn genes_iter(&self) -> impl Iterator<Item=&Gene> {
+    /// A result made of the given genes and annotations of an Index.
+    pub(crate) fn new<'a>(
+        index: &'a Index,
+        queried_genes: HashSet<&'a Gene<'a>>,
+        queried_annotations: HashSet<&'a Annotation<'a>>,
+    ) -> QueryResult<'a> {
+        QueryResult {
+            index,
+            ordered: false,
+            queried_genes,
+            queried_annotations,
+        }
+    }
+
+    pub fn genes_iter(&self) -> impl Iterator<Item=&Gene<'_>> {
         if self.ordered {
-            EitherIter::First(self.genes.iter()
+            EitherIter::First(self.index.genes.iter()
                 .filter(move |&gene| self.queried_genes.contains(gene)))
         } else {
             EitherIter::Second(self.queried_genes.iter().copied())
         }
     }
 
-    pub fn annotations_iter(&self) -> impl Iterator<Item=&Annotation> {
+    pub fn annotations_iter(&self) -> impl Iterator<Item=&Annotation<'_>> {
         if self.ordered {
-            EitherIter::First(self.annotations.iter()
+            EitherIter::First(self.index.annotations.iter()
                 .filter(move |&anno| self.queried_annotations.contains(anno)))
         } else {
             EitherIter::Second(self.queried_annotations.iter().copied())
@@ -61,7 +73,74 @@ impl QueryResult<'_> {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Set operations on the results of queries over the same Index.
+///
+/// Every queried annotation belongs to one of the queried genes, so each
+/// operation combines the genes of two results and then keeps only the
+/// annotations of the resulting genes.
+impl<'a> QueryResult<'a> {
+    /// The genes of either result, with the annotations of both.
+    pub fn union(mut self, other: QueryResult<'a>) -> QueryResult<'a> {
+        self.ordered &= other.ordered;
+        self.queried_genes.extend(other.queried_genes);
+        self.queried_annotations.extend(other.queried_annotations);
+        self
+    }
+
+    /// The genes of both results, with the annotations of both for those
+    /// genes.
+    pub fn intersection(mut self, other: QueryResult<'a>) -> QueryResult<'a> {
+        self.ordered &= other.ordered;
+        self.queried_genes.retain(|gene| other.queried_genes.contains(gene));
+        self.queried_annotations.extend(other.queried_annotations);
+        self.retain_annotations_of_genes()
+    }
+
+    /// The genes of this result which are not in the other, with the
+    /// annotations of this result for those genes.
+    pub fn difference(mut self, other: QueryResult<'a>) -> QueryResult<'a> {
+        self.queried_genes.retain(|gene| !other.queried_genes.contains(gene));
+        self.retain_annotations_of_genes()
+    }
+
+    /// Every gene of the Index which is not in this result. No annotations
+    /// are selected, since none show that a gene is not in a result.
+    pub fn complement(self) -> QueryResult<'a> {
+        let queried_genes = self.index.genes.iter()
+            .filter(|gene| !self.queried_genes.contains(gene))
+            .collect();
+        QueryResult { queried_genes, queried_annotations: HashSet::new(), ..self }
+    }
+
+    /// Keeps only the annotations which match the filter. The genes are
+    /// left as they are.
+    pub fn filter_annotations(mut self, filter: &AnnotationFilter) -> QueryResult<'a> {
+        self.queried_annotations.retain(|anno| filter.matches(anno));
+        self
+    }
+
+    /// Keeps only the genes which have at least one of the queried
+    /// annotations, such as after filtering them.
+    pub fn retain_annotated_genes(mut self) -> QueryResult<'a> {
+        let annotated: HashSet<&Gene> = self.queried_annotations.iter()
+            .filter_map(|anno| self.index.gene_of(anno))
+            .collect();
+        self.queried_genes.retain(|gene| annotated.contains(gene));
+        self
+    }
+
+    fn retain_annotations_of_genes(mut self) -> QueryResult<'a> {
+        let mut annotations = std::mem::take(&mut self.queried_annotations);
+        annotations.retain(|anno| {
+            self.index.gene_of(anno)
+                .is_some_and(|gene| self.queried_genes.contains(gene))
+        });
+        self.queried_annotations = annotations;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Segment {
     aspect: Aspect,
     annotation_status: AnnotationStatus,
@@ -82,6 +161,14 @@ impl Segment {
         Segment { aspect, annotation_status }
     }
 
+    pub fn aspect(&self) -> Aspect {
+        self.aspect
+    }
+
+    pub fn annotation_status(&self) -> AnnotationStatus {
+        self.annotation_status
+    }
+
     pub fn query<'a>(&self, index: &'a Index) -> QueryResult<'a> {
 
         // Find all genes belonging to this segment
@@ -99,32 +186,260 @@ impl Segment {
             .filter_map(|maybe_gene| maybe_gene)
             .flat_map(|(_, annos)| annos.iter().map(Deref::deref))
             .filter(|anno| anno.aspect == self.aspect
-                && anno.annotation_status == self.annotation_status)
+                && index.status_of(anno) == Some(self.annotation_status))
             .collect();
 
         QueryResult {
+            index,
             ordered: false,
-            genes: &index.genes,
-            annotations: &index.annotations,
             queried_genes,
             queried_annotations,
         }
     }
 }
 
-#[derive(Debug)]
+/// A condition which selects Genes, along with the annotations which show
+/// that they meet it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Predicate {
+    /// Genes which belong to a segment.
+    Segment(Segment),
+    /// Genes annotated to a GO term, or also to any of its descendants.
+    Term { term: String, include_descendants: bool },
+    /// Genes which are not annotated to a GO term, nor to any of its
+    /// descendants if they are included. No annotations are selected.
+    NotTerm { term: String, include_descendants: bool },
+    /// Genes of one of the gene product types (e.g. `protein_coding`). No
+    /// annotations are selected.
+    GeneProductType(Vec<String>),
+    /// Genes going by one of the names, which may be their IDs or any of
+    /// their aliases. An alias shared by more than one Gene selects all of
+    /// them (see `AliasIndex::resolve`). No annotations are selected.
+    Genes(Vec<String>),
+}
+
+impl From<Segment> for Predicate {
+    fn from(segment: Segment) -> Self {
+        Predicate::Segment(segment)
+    }
+}
+
+impl Predicate {
+    /// Finds the Genes which meet this predicate, using the TermIndex to
+    /// find the annotations to GO terms.
+    pub fn query<'a>(&self, terms: &TermIndex<'a, '_>) -> QueryResult<'a> {
+        self.query_with(terms.index(), Some(terms))
+    }
+
+    /// Finds the Genes which meet this predicate. Without a TermIndex, term
+    /// predicates only match annotations made directly to their terms.
+    fn query_with<'a>(&self, index: &'a Index, terms: Option<&TermIndex<'a, '_>>) -> QueryResult<'a> {
+        match (self, terms) {
+            (Predicate::Segment(segment), _) => segment.query(index),
+            (Predicate::GeneProductType(types), _) => {
+                let queried_genes = index.genes.iter()
+                    .filter(|gene| types.iter().any(|t| t == gene.gene_product_type))
+                    .collect();
+                QueryResult::new(index, queried_genes, HashSet::new())
+            }
+            (Predicate::Genes(names), _) => {
+                let queried_genes = names.iter()
+                    .flat_map(|name| index.aliases.resolve(name))
+                    .filter_map(|gene_id| index.anno_index.get(*gene_id).map(|&(gene, _)| gene))
+                    .collect();
+                QueryResult::new(index, queried_genes, HashSet::new())
+            }
+            (Predicate::Term { term, include_descendants }, Some(terms)) => terms.query(term, *include_descendants),
+            (Predicate::NotTerm { term, include_descendants }, Some(terms)) => {
+                let annotated = terms.genes(term, *include_descendants);
+                let queried_genes = index.anno_index.values()
+                    .map(|&(gene, _)| gene)
+                    .filter(|gene| !annotated.contains(gene))
+                    .collect();
+                QueryResult::new(index, queried_genes, HashSet::new())
+            }
+            (Predicate::Term { .. } | Predicate::NotTerm { .. }, None) => {
+                self.query_with(index, Some(&TermIndex::new(index, &Ontology::default())))
+            }
+        }
+    }
+
+    /// Whether this predicate needs a TermIndex to be answered.
+    fn needs_terms(&self) -> bool {
+        matches!(self, Predicate::Term { .. } | Predicate::NotTerm { .. })
+    }
+
+    /// Whether this predicate needs an ontology to be answered, i.e. it
+    /// includes the descendants of a GO term.
+    fn needs_ontology(&self) -> bool {
+        matches!(self,
+            Predicate::Term { include_descendants: true, .. } |
+            Predicate::NotTerm { include_descendants: true, .. })
+    }
+}
+
+/// A condition on the attributes of annotations, which narrows the
+/// annotations selected by a query.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AnnotationFilter {
+    /// Annotations to gene products of an NCBI taxon, given by its ID
+    /// without a prefix (e.g. `3702`).
+    Taxon(String),
+    /// Annotations with one of the evidence codes (e.g. `IDA`).
+    EvidenceCode(Vec<String>),
+    /// Annotations with a reference from a database, given by the prefix
+    /// of its IDs (e.g. `PMID`).
+    ReferencePrefix(String),
+    /// Annotations made by one of the databases (e.g. `TAIR`).
+    AssignedBy(Vec<String>),
+    /// Annotations made between two dates, given as YYYYMMDD and
+    /// including both.
+    Date { from: Option<u32>, to: Option<u32> },
+}
+
+impl AnnotationFilter {
+    pub fn matches(&self, annotation: &Annotation) -> bool {
+        match self {
+            AnnotationFilter::Taxon(taxon) => {
+                // Any further taxa are those of interacting organisms
+                let first = annotation.taxon.split('|').next().unwrap_or_default();
+                first.strip_prefix("taxon:").unwrap_or(first) == taxon
+            }
+            AnnotationFilter::EvidenceCode(codes) => codes.iter().any(|code| code == annotation.evidence_code),
+            AnnotationFilter::ReferencePrefix(prefix) => annotation.reference.split('|')
+                .any(|reference| reference.split(':').next() == Some(prefix)),
+            AnnotationFilter::AssignedBy(databases) => databases.iter().any(|db| db == annotation.assigned_by),
+            AnnotationFilter::Date { from, to } => match annotation.date.parse::<u32>() {
+                Ok(date) => from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to),
+                Err(_) => false,
+            },
+        }
+    }
+}
+
+/// A query over an Index, which selects Genes along with the annotations
+/// which show that they meet it.
+///
+/// Queries can be nested, e.g. `(F,EXP | P,EXP) & !C,UNANNOTATED` is:
+///
+/// ```
+/// # use ifad::{Aspect::*, AnnotationStatus::*, Query, Segment};
+/// let query = Query::And(vec![
+///     Query::Or(vec![
+///         Segment::new(MolecularFunction, KnownExperimental).into(),
+///         Segment::new(BiologicalProcess, KnownExperimental).into(),
+///     ]),
+///     Query::Not(Box::new(Segment::new(CellularComponent, Unannotated).into())),
+/// ]);
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Query {
     All,
-    Union(Vec<Segment>),
-    Intersection(Vec<Segment>),
+    Union(Vec<Predicate>),
+    Intersection(Vec<Predicate>),
+    /// The Genes which meet a single predicate.
+    Predicate(Predicate),
+    /// The Genes selected by every one of the queries.
+    And(Vec<Query>),
+    /// The Genes selected by any of the queries.
+    Or(Vec<Query>),
+    /// The Genes which are not selected by the query. No annotations are
+    /// selected.
+    Not(Box<Query>),
+    /// The Genes selected by the first query but not by the second, with
+    /// the annotations selected by the first.
+    Difference(Box<Query>, Box<Query>),
+    /// The Genes selected by the query, with only those of its annotations
+    /// which match the filter.
+    Filter(Box<Query>, AnnotationFilter),
+    /// The Genes selected by the query which have at least one of its
+    /// selected annotations. Genes selected without annotations, such as
+    /// Unannotated ones, are left out.
+    Annotated(Box<Query>),
+}
+
+impl From<Predicate> for Query {
+    fn from(predicate: Predicate) -> Self {
+        Query::Predicate(predicate)
+    }
+}
+
+impl From<Segment> for Query {
+    fn from(segment: Segment) -> Self {
+        Query::Predicate(segment.into())
+    }
 }
 
 impl Query {
-    pub fn execute<'a>(&self, index: &'a Index) -> QueryResult<'a> {
+    /// Executes this query without an ontology. Returns None if any term
+    /// predicate includes the descendants of its term, which can only be
+    /// found with an ontology (see `execute_with`).
+    pub fn execute<'a>(&self, index: &'a Index) -> Option<QueryResult<'a>> {
+        if self.needs_ontology() {
+            return None;
+        }
+        Some(self.evaluate(index, None))
+    }
+
+    /// Executes this query, using the ontology to find annotations to the
+    /// descendants of the terms in term predicates.
+    pub fn execute_with<'a>(&self, index: &'a Index, ontology: &Ontology) -> QueryResult<'a> {
+        // Only propagate annotations to GO terms if they will be used
+        let terms = if self.needs_terms() {
+            Some(TermIndex::new(index, ontology))
+        } else {
+            None
+        };
+        self.evaluate(index, terms.as_ref())
+    }
+
+    fn evaluate<'a>(&self, index: &'a Index, terms: Option<&TermIndex<'a, '_>>) -> QueryResult<'a> {
         match self {
             Query::All => query_all(index),
-            Query::Union(segments) => query_union(index, &segments),
-            Query::Intersection(segments) => query_intersection(index, &segments),
+            Query::Union(predicates) => query_union(index, predicates.iter()
+                .map(|predicate| predicate.query_with(index, terms))),
+            Query::Intersection(predicates) => query_intersection(index, predicates.iter()
+                .map(|predicate| predicate.query_with(index, terms))),
+            Query::Predicate(predicate) => predicate.query_with(index, terms),
+            Query::And(queries) => query_intersection(index, queries.iter()
+                .map(|query| query.evaluate(index, terms))),
+            Query::Or(queries) => query_union(index, queries.iter()
+                .map(|query| query.evaluate(index, terms))),
+            Query::Not(query) => query.evaluate(index, terms).complement(),
+            Query::Difference(query, other) => query.evaluate(index, terms)
+                .difference(other.evaluate(index, terms)),
+            Query::Filter(query, filter) => query.evaluate(index, terms).filter_annotations(filter),
+            Query::Annotated(query) => query.evaluate(index, terms).retain_annotated_genes(),
+        }
+    }
+
+    /// Whether any predicate of this query needs a TermIndex to be answered.
+    fn needs_terms(&self) -> bool {
+        match self {
+            Query::All => false,
+            Query::Union(predicates) | Query::Intersection(predicates) => {
+                predicates.iter().any(Predicate::needs_terms)
+            }
+            Query::Predicate(predicate) => predicate.needs_terms(),
+            Query::And(queries) | Query::Or(queries) => queries.iter().any(Query::needs_terms),
+            Query::Not(query) => query.needs_terms(),
+            Query::Difference(query, other) => query.needs_terms() || other.needs_terms(),
+            Query::Filter(query, _) | Query::Annotated(query) => query.needs_terms(),
+        }
+    }
+
+    /// Whether any term predicate includes the descendants of its term.
+    pub fn needs_ontology(&self) -> bool {
+        match self {
+            Query::All => false,
+            Query::Union(predicates) | Query::Intersection(predicates) => {
+                predicates.iter().any(Predicate::needs_ontology)
+            }
+            Query::Predicate(predicate) => predicate.needs_ontology(),
+            Query::And(queries) | Query::Or(queries) => queries.iter().any(Query::needs_ontology),
+            Query::Not(query) => query.needs_ontology(),
+            Query::Difference(query, other) => query.needs_ontology() || other.needs_ontology(),
+            Query::Filter(query, _) | Query::Annotated(query) => query.needs_ontology(),
         }
     }
 }
@@ -140,72 +455,34 @@ fn query_all<'a>(index: &'a Index) -> QueryResult<'a> {
         .collect();
 
     QueryResult {
+        index,
         ordered: true,
-        genes: &index.genes,
-        annotations: &index.annotations,
         queried_genes,
         queried_annotations,
     }
 }
 
-fn query_union<'a>(index: &'a Index, segments: &[Segment]) -> QueryResult<'a> {
-    let mut union_genes = HashSet::new();
-    let mut union_annos = HashSet::new();
-
-    for segment in segments {
-        let QueryResult { queried_genes, queried_annotations, .. } = segment.query(index);
-        union_genes.extend(queried_genes);
-        union_annos.extend(queried_annotations);
-    }
-
-    QueryResult {
-        ordered: false,
-        genes: &index.genes,
-        annotations: &index.annotations,
-        queried_genes: union_genes,
-        queried_annotations: union_annos,
-    }
+fn query_union<'a, I>(index: &'a Index, results: I) -> QueryResult<'a>
+    where I: Iterator<Item=QueryResult<'a>>
+{
+    results.fold(QueryResult::empty(index), QueryResult::union)
 }
 
-fn query_intersection<'a>(index: &'a Index, segments: &[Segment]) -> QueryResult<'a> {
-    if segments.len() == 0 { return QueryResult::empty(&index); }
-    if segments.len() == 1 { return segments[0].query(index); }
-
-    let mut segment_query_results = segments.iter()
-        .map(|segment| segment.query(&index));
-
-    let head = segment_query_results.nth(0).expect("should get first segment in intersection");
-    let rest = segment_query_results;
-
-    let mut gene_set: HashSet<&Gene> = head.queried_genes;
-    let mut anno_set: HashSet<&Annotation> = head.queried_annotations;
-
-    // Take the intersection of genes, but the union of annotations
-    for segment in rest {
-        gene_set = gene_set.intersection(&segment.queried_genes).copied().collect();
-        anno_set = anno_set.union(&segment.queried_annotations).copied().collect();
-    }
-
-    // Only keep annotations whose genes appear in the gene_set
-    anno_set = anno_set.into_iter().filter(|anno| {
-        anno.gene_in(&index.anno_index)
-            .map(|gene| gene_set.contains(gene))
-            .unwrap_or(false)
-    }).collect();
-
-    QueryResult {
-        ordered: false,
-        genes: &index.genes,
-        annotations: &index.annotations,
-        queried_genes: gene_set,
-        queried_annotations: anno_set,
+/// The intersection of all of the results, or an empty result if there are
+/// none.
+fn query_intersection<'a, I>(index: &'a Index, mut results: I) -> QueryResult<'a>
+    where I: Iterator<Item=QueryResult<'a>>
+{
+    match results.next() {
+        Some(head) => results.fold(head, QueryResult::intersection),
+        None => QueryResult::empty(index),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{AnnotationRecord, GeneRecord};
+    use crate::{AnnotationRecord, GeneRecord, EvidencePolicy};
 
     lazy_static! {
         static ref TEST_GENE_RECORDS: Vec<GeneRecord> = vec![
@@ -278,16 +555,15 @@ mod tests {
             /* 46 */ AnnotationRecord { db: "TAIR".to_string(), database_id: "locus:4515103469".to_string(), db_object_symbol: "AT4G30872".to_string(), invert: "".to_string(),    go_term: "GO:0008150".to_string(), reference: "TAIR:Communication:1345790".to_string(),                evidence_code: "ND".to_string(),  /* Unknown           */ additional_evidence: "".to_string(),                                                                     aspect: Aspect::BiologicalProcess,  unique_gene_name: "AT4G30872".to_string(),                           alternative_gene_name: "AT4G30872".to_string(),                                                                                         gene_product_type: "RNA".to_string(),     taxon: "taxon:3702".to_string(), date: "20090508".to_string(), assigned_by: "TAIR".to_string(),       annotation_extension: "".to_string(), gene_product_form_id: "TAIR:locus:4515103469".to_string() },
         ];
 
-        static ref EVIDENCE_CODES: &'static [&'static str] = &["EXP", "IDA", "IPI", "IMP", "IGI", "IEP", "HTP", "HDA", "HMP", "HGI", "HEP"];
         static ref TEST_ANNOTATIONS: Vec<Annotation<'static>> = TEST_ANNOTATION_RECORDS.iter()
-            .map(|record| Annotation::from_record(record, &EVIDENCE_CODES))
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
             .collect();
     }
 
     #[test]
     fn test_query_all() {
         let index = Index::new(&*TEST_GENES, &*TEST_ANNOTATIONS);
-        let result = Query::All.execute(&index);
+        let result = Query::All.execute(&index).unwrap();
 
         // All of the genes from the input should appear in the query result
         assert!(TEST_GENES.iter().all(|gene| result.queried_genes.contains(gene)));
@@ -362,8 +638,8 @@ mod tests {
         let segment_a = Segment { aspect: BiologicalProcess, annotation_status: KnownExperimental };
         let segment_b = Segment { aspect: MolecularFunction, annotation_status: KnownOther };
         let segment_c = Segment { aspect: CellularComponent, annotation_status: KnownOther };
-        let query = Query::Union(vec![segment_a, segment_b, segment_c]);
-        let results = query.execute(&index);
+        let query = Query::Union(vec![segment_a.into(), segment_b.into(), segment_c.into()]);
+        let results = query.execute(&index).unwrap();
 
         let expected_genes_vec = vec![
             &TEST_GENES[0],
@@ -411,8 +687,8 @@ mod tests {
         let segment_a = Segment { aspect: BiologicalProcess, annotation_status: Unknown };
         let segment_b = Segment { aspect: MolecularFunction, annotation_status: Unknown };
         let segment_c = Segment { aspect: CellularComponent, annotation_status: Unknown };
-        let query = Query::Union(vec![segment_a, segment_b, segment_c]);
-        let results = query.execute(&index);
+        let query = Query::Union(vec![segment_a.into(), segment_b.into(), segment_c.into()]);
+        let results = query.execute(&index).unwrap();
 
         let expected_genes_vec = vec![
             &TEST_GENES[3],
@@ -439,7 +715,7 @@ mod tests {
     fn test_query_all_is_ordered() {
         let index = Index::new(&*TEST_GENES, &*TEST_ANNOTATIONS);
         let query = Query::All;
-        let results = query.execute(&index);
+        let results = query.execute(&index).unwrap();
 
         // Test that annotations are in the same order
         results.annotations_iter().zip(TEST_ANNOTATIONS.iter())
@@ -454,8 +730,8 @@ mod tests {
         let segment_a = Segment { aspect: CellularComponent, annotation_status: KnownOther };
         let segment_b = Segment { aspect: MolecularFunction, annotation_status: Unknown };
         let segment_c = Segment { aspect: BiologicalProcess, annotation_status: Unknown };
-        let query = Query::Intersection(vec![segment_a, segment_b, segment_c]);
-        let results = query.execute(&index);
+        let query = Query::Intersection(vec![segment_a.into(), segment_b.into(), segment_c.into()]);
+        let results = query.execute(&index).unwrap();
 
         let expected_genes_vec = vec![
             // AT2G34580
@@ -482,8 +758,8 @@ mod tests {
         let index = Index::new(&*TEST_GENES, &*TEST_ANNOTATIONS);
         let segment_a = Segment { aspect: CellularComponent, annotation_status: KnownOther };
         let segment_b = Segment { aspect: CellularComponent, annotation_status: Unknown };
-        let query = Query::Intersection(vec![segment_a, segment_b]);
-        let results = query.execute(&index);
+        let query = Query::Intersection(vec![segment_a.into(), segment_b.into()]);
+        let results = query.execute(&index).unwrap();
 
         let expected_genes = HashSet::new();
         assert_eq!(&expected_genes, &results.queried_genes);
@@ -491,4 +767,207 @@ mod tests {
         let expected_annotations = HashSet::new();
         assert_eq!(&expected_annotations, &results.queried_annotations);
     }
+
+    #[test]
+    fn test_query_terms() {
+        use {Aspect::*, AnnotationStatus::*};
+
+        let go_obo = "[Term]
+id: GO:0043226
+name: organelle
+namespace: cellular_component
+
+[Term]
+id: GO:0005739
+name: mitochondrion
+namespace: cellular_component
+is_a: GO:0043226 ! organelle
+
+[Term]
+id: GO:0009536
+name: plastid
+namespace: cellular_component
+is_a: GO:0043226 ! organelle
+
+[Term]
+id: GO:0009507
+name: chloroplast
+namespace: cellular_component
+is_a: GO:0009536 ! plastid
+";
+        let ontology = Ontology::parse_from(std::io::Cursor::new(go_obo)).unwrap();
+        let index = Index::new(&*TEST_GENES, &*TEST_ANNOTATIONS);
+        let term = |term: &str| Predicate::Term { term: term.to_string(), include_descendants: true };
+        let not_term = |term: &str| Predicate::NotTerm { term: term.to_string(), include_descendants: true };
+
+        // Without the ontology, descendants can't be found and only exact terms match
+        assert!(Query::Union(vec![term("GO:0005739")]).execute(&index).is_none());
+        let exact = Predicate::Term { term: "GO:0005739".to_string(), include_descendants: false };
+        let results = Query::Union(vec![exact]).execute(&index).unwrap();
+        let expected_genes: HashSet<&Gene> = TEST_GENES[1..4].iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+        let expected_annotations: HashSet<&Annotation> = [12, 15, 16, 18, 22, 36, 40, 43].iter()
+            .map(|&i| &TEST_ANNOTATIONS[i])
+            .collect();
+        assert_eq!(&expected_annotations, &results.queried_annotations);
+        let exact = Predicate::Term { term: "GO:0043226".to_string(), include_descendants: false };
+        assert!(Query::Union(vec![exact]).execute(&index).unwrap().queried_genes.is_empty());
+
+        // Organelles include mitochondria and chloroplasts, which are plastids
+        let results = Query::Union(vec![term("GO:0043226")]).execute_with(&index, &ontology);
+        let expected_genes: HashSet<&Gene> = TEST_GENES[0..4].iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+        assert_eq!(results.queried_annotations.len(), 13);
+
+        let query = Query::Intersection(vec![term("GO:0043226"), not_term("GO:0005739")]);
+        let results = query.execute_with(&index, &ontology);
+        let expected_genes: HashSet<&Gene> = vec![&TEST_GENES[0]].into_iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+        let expected_annotations: HashSet<&Annotation> = vec![&TEST_ANNOTATIONS[3]].into_iter().collect();
+        assert_eq!(&expected_annotations, &results.queried_annotations);
+
+        let segment = Segment { aspect: CellularComponent, annotation_status: KnownExperimental };
+        let query = Query::Intersection(vec![segment.into(), term("GO:0005739")]);
+        let results = query.execute_with(&index, &ontology);
+        let expected_genes: HashSet<&Gene> = vec![&TEST_GENES[2]].into_iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+        assert!(results.queried_annotations.contains(&TEST_ANNOTATIONS[36]));
+    }
+
+    #[test]
+    fn test_query_expression() {
+        use {Aspect::*, AnnotationStatus::*};
+
+        let index = Index::new(&*TEST_GENES, &*TEST_ANNOTATIONS);
+        let segment = |aspect, annotation_status| Query::from(Segment { aspect, annotation_status });
+
+        // (F,EXP | P,EXP) & !C,OTHER
+        let query = Query::And(vec![
+            Query::Or(vec![segment(MolecularFunction, KnownExperimental), segment(BiologicalProcess, KnownExperimental)]),
+            Query::Not(Box::new(segment(CellularComponent, KnownOther))),
+        ]);
+        let results = query.execute(&index).unwrap();
+        let expected_genes: HashSet<&Gene> = vec![&TEST_GENES[0], &TEST_GENES[2]].into_iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+        let expected_annotations: HashSet<&Annotation> = [7, 9, 24, 25, 31, 34, 35, 39].iter()
+            .map(|&i| &TEST_ANNOTATIONS[i])
+            .collect();
+        assert_eq!(&expected_annotations, &results.queried_annotations);
+
+        // C,OTHER - F,UNKNOWN
+        let query = Query::Difference(
+            Box::new(segment(CellularComponent, KnownOther)),
+            Box::new(segment(MolecularFunction, Unknown)),
+        );
+        let results = query.execute(&index).unwrap();
+        let expected_genes: HashSet<&Gene> = vec![&TEST_GENES[1]].into_iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+        let expected_annotations: HashSet<&Annotation> = [12, 15, 16, 18].iter()
+            .map(|&i| &TEST_ANNOTATIONS[i])
+            .collect();
+        assert_eq!(&expected_annotations, &results.queried_annotations);
+    }
+
+    #[test]
+    fn test_query_filters() {
+        use {Aspect::*, AnnotationStatus::*};
+
+        assert!(AnnotationFilter::ReferencePrefix("PMID".to_string()).matches(&TEST_ANNOTATIONS[0]));
+        assert!(!AnnotationFilter::ReferencePrefix("PMID".to_string()).matches(&TEST_ANNOTATIONS[1]));
+        assert!(AnnotationFilter::ReferencePrefix("TAIR".to_string()).matches(&TEST_ANNOTATIONS[3]));
+        assert!(AnnotationFilter::EvidenceCode(vec!["IDA".to_string(), "HDA".to_string()]).matches(&TEST_ANNOTATIONS[3]));
+        assert!(AnnotationFilter::Taxon("3702".to_string()).matches(&TEST_ANNOTATIONS[3]));
+
+        // TAIR-curated annotations since 2018
+        let index = Index::new(&*TEST_GENES, &*TEST_ANNOTATIONS);
+        let segment = Segment { aspect: BiologicalProcess, annotation_status: KnownExperimental };
+        let query = Query::Filter(
+            Box::new(Query::Filter(Box::new(segment.into()), AnnotationFilter::AssignedBy(vec!["TAIR".to_string()]))),
+            AnnotationFilter::Date { from: Some(20180101), to: None },
+        );
+        let results = query.execute(&index).unwrap();
+        let expected_genes: HashSet<&Gene> = TEST_GENES[0..3].iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+        let expected_annotations: HashSet<&Annotation> = vec![&TEST_ANNOTATIONS[34]].into_iter().collect();
+        assert_eq!(&expected_annotations, &results.queried_annotations);
+
+        // Only the genes with those annotations
+        let results = Query::Annotated(Box::new(query)).execute(&index).unwrap();
+        let expected_genes: HashSet<&Gene> = vec![&TEST_GENES[2]].into_iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+        assert_eq!(&expected_annotations, &results.queried_annotations);
+    }
+
+    #[test]
+    fn test_query_gene_product_type() {
+        use {Aspect::*, AnnotationStatus::*};
+
+        let index = Index::new(&*TEST_GENES, &*TEST_ANNOTATIONS);
+        let segment = Query::from(Segment { aspect: CellularComponent, annotation_status: Unknown });
+        let rna = Query::from(Predicate::GeneProductType(vec!["other_rna".to_string()]));
+
+        let results = Query::And(vec![segment.clone(), rna.clone()]).execute(&index).unwrap();
+        let expected_genes: HashSet<&Gene> = vec![&TEST_GENES[4]].into_iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+        let expected_annotations: HashSet<&Annotation> = vec![&TEST_ANNOTATIONS[44]].into_iter().collect();
+        assert_eq!(&expected_annotations, &results.queried_annotations);
+
+        let results = Query::And(vec![segment, Query::Not(Box::new(rna))]).execute(&index).unwrap();
+        assert!(results.queried_genes.is_empty());
+        assert!(results.queried_annotations.is_empty());
+
+        let counts = index.product_type_counts();
+        assert_eq!(counts["other_rna"][&CellularComponent][&Unknown], 1);
+        assert_eq!(counts["protein_coding"][&CellularComponent][&KnownOther], 2);
+        assert!(!counts["protein_coding"][&CellularComponent].contains_key(&Unknown));
+    }
+
+    #[test]
+    fn test_query_genes() {
+        use AnnotationStatus::*;
+        use Aspect::*;
+
+        let index = Index::new(&*TEST_GENES, &*TEST_ANNOTATIONS);
+        let names = vec!["SAD1".to_string(), "F10K1.23".to_string(), "NOT_A_GENE".to_string()];
+        let genes = Query::from(Predicate::Genes(names));
+
+        let results = genes.execute(&index).unwrap();
+        let expected_genes: HashSet<&Gene> = vec![&TEST_GENES[0], &TEST_GENES[1]].into_iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+        assert!(results.queried_annotations.is_empty());
+
+        let segment = Query::from(Segment { aspect: MolecularFunction, annotation_status: KnownExperimental });
+        let results = Query::And(vec![segment, genes]).execute(&index).unwrap();
+        let expected_genes: HashSet<&Gene> = vec![&TEST_GENES[1]].into_iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+    }
+
+    #[test]
+    fn test_query_alias_annotations() {
+        use {Aspect::*, AnnotationStatus::*};
+
+        // The HDA annotation of AT5G48870 only names it by its aliases
+        let mut records = TEST_ANNOTATION_RECORDS.clone();
+        records[3] = AnnotationRecord {
+            unique_gene_name: "SAD1".to_string(),
+            alternative_gene_name: "AtSAD1|LSM5".to_string(),
+            ..records[3].clone()
+        };
+        let annotations: Vec<Annotation> = records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .collect();
+        let index = Index::new(&*TEST_GENES, &annotations);
+
+        let segment = Query::from(Segment { aspect: CellularComponent, annotation_status: KnownExperimental });
+        let genes = Query::from(Predicate::Genes(vec!["AT5G48870".to_string()]));
+        let results = Query::And(vec![segment.clone(), genes]).execute(&index).unwrap();
+        let expected_genes: HashSet<&Gene> = vec![&TEST_GENES[0]].into_iter().collect();
+        let expected_annotations: HashSet<&Annotation> = vec![&annotations[3]].into_iter().collect();
+        assert_eq!(&expected_genes, &results.queried_genes);
+        assert_eq!(&expected_annotations, &results.queried_annotations);
+
+        let query = Query::Filter(Box::new(segment), AnnotationFilter::EvidenceCode(vec!["HDA".to_string()]));
+        let results = Query::Annotated(Box::new(query)).execute(&index).unwrap();
+        assert!(results.queried_genes.contains(&TEST_GENES[0]));
+    }
 }
@@ -0,0 +1,532 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use crate::{AnnotationFilter, AnnotationStatus, Aspect, Predicate, Query, Segment};
+
+/// An error in the text of a query, along with the part of the text at
+/// fault.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    /// The byte offsets of the offending text in the query.
+    pub span: Range<usize>,
+}
+
+impl QueryError {
+    fn new<S: Into<String>>(message: S, span: Range<usize>) -> QueryError {
+        QueryError { message: message.into(), span }
+    }
+
+    /// Shows the query with the offending text underlined, e.g.
+    ///
+    /// ```text
+    /// F:EXP and P:EXPERIMENTAL
+    ///             ^^^^^^^^^^^^ unknown annotation status `EXPERIMENTAL`
+    /// ```
+    pub fn highlight(&self, query: &str) -> String {
+        let start = query[..self.span.start].chars().count();
+        let width = query[self.span.clone()].chars().count().max(1);
+        format!("{}\n{}{} {}", query, " ".repeat(start), "^".repeat(width), self.message)
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.message, self.span.start, self.span.end)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl Query {
+    /// Parses a query from text, such as
+    /// `F:EXP and (P:OTHER or P:EXP) and not C:UNANNOTATED and taxon = 3702`.
+    ///
+    /// Queries combine segments (`F:EXP`), GO terms (`GO:0015979`, or
+    /// `exact GO:0015979` to leave out annotations to its descendants), and
+    /// gene product types (`type = protein_coding` or `type in (A, B)`), and
+    /// genes by name (`gene = ENO1` or `gene in (ENO1, AT2G36530)`) with
+    /// `and`, `or`, and `not` (or `&`, `|`, and `!`), where `and` binds
+    /// tighter than `or`. Filters on annotations narrow the annotations of
+    /// the queries they are combined with by `and`:
+    ///
+    /// - `taxon = 3702`
+    /// - `evidence = IDA` or `evidence in (IDA, IMP)`
+    /// - `reference = PMID`, for references with the prefix `PMID:`
+    /// - `assigned_by = TAIR` or `assigned_by in (TAIR, UniProt)`
+    /// - `date >= 20180101`, also with `=`, `<`, `<=`, or `>`
+    pub fn parse(text: &str) -> Result<Query, QueryError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, position: 0, end: text.len() };
+        let operand = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(QueryError::new(format!("expected `and` or `or`, found {}", token.kind), token.span.clone()));
+        }
+        Ok(operand.into_query())
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Query::parse(text)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum TokenKind {
+    And,
+    Or,
+    Not,
+    Equals,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Comma,
+    Open,
+    Close,
+    Word(String),
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::And => write!(f, "`and`"),
+            TokenKind::Or => write!(f, "`or`"),
+            TokenKind::Not => write!(f, "`not`"),
+            TokenKind::Equals => write!(f, "`=`"),
+            TokenKind::Less => write!(f, "`<`"),
+            TokenKind::LessOrEqual => write!(f, "`<=`"),
+            TokenKind::Greater => write!(f, "`>`"),
+            TokenKind::GreaterOrEqual => write!(f, "`>=`"),
+            TokenKind::Comma => write!(f, "`,`"),
+            TokenKind::Open => write!(f, "`(`"),
+            TokenKind::Close => write!(f, "`)`"),
+            TokenKind::Word(word) => write!(f, "`{}`", word),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    span: Range<usize>,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == ':' || c == '_' || c == '.' || c == '-'
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        let kind = match c {
+            '(' => TokenKind::Open,
+            ')' => TokenKind::Close,
+            ',' => TokenKind::Comma,
+            '!' => TokenKind::Not,
+            '<' | '>' => {
+                let or_equal = chars.peek().map(|&(_, next)| next) == Some('=');
+                if or_equal {
+                    chars.next();
+                }
+                match (c, or_equal) {
+                    ('<', false) => TokenKind::Less,
+                    ('<', true) => TokenKind::LessOrEqual,
+                    ('>', false) => TokenKind::Greater,
+                    _ => TokenKind::GreaterOrEqual,
+                }
+            }
+            '&' | '|' | '=' => {
+                // The symbol may be doubled, as in `&&` or `==`
+                if chars.peek().map(|&(_, next)| next) == Some(c) {
+                    chars.next();
+                }
+                match c {
+                    '&' => TokenKind::And,
+                    '|' => TokenKind::Or,
+                    _ => TokenKind::Equals,
+                }
+            }
+            c if is_word_char(c) => {
+                while chars.peek().is_some_and(|&(_, next)| is_word_char(next)) {
+                    chars.next();
+                }
+                let end = chars.peek().map_or(text.len(), |&(i, _)| i);
+                let word = &text[start..end];
+                match &*word.to_lowercase() {
+                    "and" => TokenKind::And,
+                    "or" => TokenKind::Or,
+                    "not" => TokenKind::Not,
+                    _ => TokenKind::Word(word.to_string()),
+                }
+            }
+            c => return Err(QueryError::new(format!("unexpected character `{}`", c), start..start + c.len_utf8())),
+        };
+        let end = chars.peek().map_or(text.len(), |&(i, _)| i);
+        tokens.push(Token { kind, span: start..end });
+    }
+    Ok(tokens)
+}
+
+/// A parsed part of a query: either a query, or filters which must be
+/// combined with a query by `and`.
+enum Operand {
+    Query(Query),
+    Filters(Vec<AnnotationFilter>, Range<usize>),
+}
+
+impl Operand {
+    /// Filters on their own apply to every gene.
+    fn into_query(self) -> Query {
+        match self {
+            Operand::Query(query) => query,
+            Operand::Filters(filters, _) => filter(Query::All, filters),
+        }
+    }
+
+    fn into_combinable(self) -> Result<Query, QueryError> {
+        match self {
+            Operand::Query(query) => Ok(query),
+            Operand::Filters(_, span) => Err(QueryError::new("filters can only be combined with `and`", span)),
+        }
+    }
+}
+
+fn filter(query: Query, filters: Vec<AnnotationFilter>) -> Query {
+    filters.into_iter().fold(query, |query, filter| Query::Filter(Box::new(query), filter))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// The length of the text, for errors at its end.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<&Token, QueryError> {
+        let end = self.end;
+        let token = self.tokens.get(self.position)
+            .ok_or_else(|| QueryError::new("unexpected end of query", end..end))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn next_is(&self, kind: &TokenKind) -> bool {
+        self.peek().is_some_and(|token| &token.kind == kind)
+    }
+
+    fn parse_or(&mut self) -> Result<Operand, QueryError> {
+        let first = self.parse_and()?;
+        if !self.next_is(&TokenKind::Or) {
+            return Ok(first);
+        }
+
+        let mut queries = vec![first.into_combinable()?];
+        while self.next_is(&TokenKind::Or) {
+            self.position += 1;
+            queries.push(self.parse_and()?.into_combinable()?);
+        }
+        Ok(Operand::Query(Query::Or(queries)))
+    }
+
+    fn parse_and(&mut self) -> Result<Operand, QueryError> {
+        let mut queries = Vec::new();
+        let mut filters = Vec::new();
+        let mut filter_span: Option<Range<usize>> = None;
+        loop {
+            match self.parse_not()? {
+                Operand::Query(query) => queries.push(query),
+                Operand::Filters(more, span) => {
+                    filters.extend(more);
+                    let start = filter_span.map_or(span.start, |filter_span| filter_span.start);
+                    filter_span = Some(start..span.end);
+                }
+            }
+            if !self.next_is(&TokenKind::And) {
+                break;
+            }
+            self.position += 1;
+        }
+
+        let query = match queries.len() {
+            0 => return Ok(Operand::Filters(filters, filter_span.unwrap_or_default())),
+            1 => queries.remove(0),
+            _ => Query::And(queries),
+        };
+        Ok(Operand::Query(filter(query, filters)))
+    }
+
+    fn parse_not(&mut self) -> Result<Operand, QueryError> {
+        if self.next_is(&TokenKind::Not) {
+            self.position += 1;
+            let query = self.parse_not()?.into_combinable()?;
+            return Ok(Operand::Query(Query::Not(Box::new(query))));
+        }
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<Operand, QueryError> {
+        let token = self.next()?;
+        let (kind, span) = (token.kind.clone(), token.span.clone());
+        let word = match kind {
+            TokenKind::Open => {
+                let operand = self.parse_or()?;
+                let close = self.next().map_err(|_| QueryError::new("unclosed `(`", span))?;
+                if close.kind != TokenKind::Close {
+                    return Err(QueryError::new(format!("expected `)`, found {}", close.kind), close.span.clone()));
+                }
+                return Ok(operand);
+            }
+            TokenKind::Word(word) => word,
+            kind => {
+                return Err(QueryError::new(format!("expected a segment, GO term, or filter, found {}", kind), span));
+            }
+        };
+
+        match &*word.to_lowercase() {
+            "all" => Ok(Operand::Query(Query::All)),
+            "exact" => {
+                let token = self.next()?;
+                match &token.kind {
+                    TokenKind::Word(term) if is_go_term(term) => Ok(Operand::Query(Query::Predicate(
+                        Predicate::Term { term: term.to_string(), include_descendants: false }))),
+                    kind => Err(QueryError::new(format!("expected a GO term, found {}", kind), token.span.clone())),
+                }
+            }
+            "type" | "gene" => {
+                let operator = self.next()?;
+                let (operator, operator_span) = (operator.kind.clone(), operator.span.clone());
+                let is_type = word.eq_ignore_ascii_case("type");
+                let expected = if is_type { "a gene product type" } else { "a gene name" };
+                let values = match operator {
+                    TokenKind::Equals => vec![self.expect_word(expected)?.0],
+                    TokenKind::Word(word) if word.eq_ignore_ascii_case("in") => self.parse_list()?.0,
+                    operator => {
+                        return Err(QueryError::new(format!("expected `=` or `in`, found {}", operator), operator_span));
+                    }
+                };
+                let predicate = match is_type {
+                    true => Predicate::GeneProductType(values),
+                    false => Predicate::Genes(values),
+                };
+                Ok(Operand::Query(Query::Predicate(predicate)))
+            }
+            "taxon" | "evidence" | "reference" | "assigned_by" | "date" => {
+                let (filter, end) = self.parse_filter(&word.to_lowercase())?;
+                Ok(Operand::Filters(vec![filter], span.start..end))
+            }
+            _ if is_go_term(&word) => Ok(Operand::Query(Query::Predicate(
+                Predicate::Term { term: word, include_descendants: true }))),
+            _ => parse_segment(&word, span).map(|segment| Operand::Query(segment.into())),
+        }
+    }
+}
+
+impl Parser {
+    /// Parses the rest of a filter on an attribute of annotations, returning
+    /// the filter and the end of its text.
+    fn parse_filter(&mut self, attribute: &str) -> Result<(AnnotationFilter, usize), QueryError> {
+        let operator = self.next()?;
+        let (operator, operator_span) = (operator.kind.clone(), operator.span.clone());
+        let expected = match attribute {
+            "date" => "`=`, `<`, `<=`, `>`, or `>=`",
+            "evidence" | "assigned_by" => "`=` or `in`",
+            _ => "`=`",
+        };
+        let unexpected = |operator: &TokenKind| {
+            QueryError::new(format!("expected {}, found {}", expected, operator), operator_span.clone())
+        };
+
+        // Lists of values may be given as `in (A, B)`
+        if let TokenKind::Word(word) = &operator {
+            if word.eq_ignore_ascii_case("in") && (attribute == "evidence" || attribute == "assigned_by") {
+                let (values, end) = self.parse_list()?;
+                let filter = match attribute {
+                    "evidence" => AnnotationFilter::EvidenceCode(values),
+                    _ => AnnotationFilter::AssignedBy(values),
+                };
+                return Ok((filter, end));
+            }
+            return Err(unexpected(&operator));
+        }
+
+        let (value, value_span) = self.expect_word("a value")?;
+        let end = value_span.end;
+        if attribute == "date" {
+            let date = match value.parse::<u32>() {
+                Ok(date) if value.len() == 8 => date,
+                _ => return Err(QueryError::new(format!("invalid date `{}`, expected YYYYMMDD", value), value_span)),
+            };
+            let (from, to) = match operator {
+                TokenKind::Equals => (Some(date), Some(date)),
+                TokenKind::Less => (None, Some(date.saturating_sub(1))),
+                TokenKind::LessOrEqual => (None, Some(date)),
+                TokenKind::Greater => (Some(date + 1), None),
+                TokenKind::GreaterOrEqual => (Some(date), None),
+                operator => return Err(unexpected(&operator)),
+            };
+            return Ok((AnnotationFilter::Date { from, to }, end));
+        }
+
+        if operator != TokenKind::Equals {
+            return Err(unexpected(&operator));
+        }
+        let filter = match attribute {
+            "taxon" => {
+                let taxon = value.strip_prefix("taxon:")
+                    .or_else(|| value.strip_prefix("NCBITaxon:"))
+                    .unwrap_or(&value);
+                if taxon.is_empty() || !taxon.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(QueryError::new(format!("invalid taxon ID `{}`", taxon), value_span));
+                }
+                AnnotationFilter::Taxon(taxon.to_string())
+            }
+            "evidence" => AnnotationFilter::EvidenceCode(vec![value]),
+            "assigned_by" => AnnotationFilter::AssignedBy(vec![value]),
+            _ => AnnotationFilter::ReferencePrefix(value.trim_end_matches(':').to_string()),
+        };
+        Ok((filter, end))
+    }
+
+    /// Parses a list of values like `(A, B)`, returning them and the end of
+    /// its text.
+    fn parse_list(&mut self) -> Result<(Vec<String>, usize), QueryError> {
+        let open = self.next()?;
+        if open.kind != TokenKind::Open {
+            return Err(QueryError::new(format!("expected `(`, found {}", open.kind), open.span.clone()));
+        }
+        let mut values = Vec::new();
+        loop {
+            values.push(self.expect_word("a value")?.0);
+            let token = self.next()?;
+            match token.kind {
+                TokenKind::Comma => continue,
+                TokenKind::Close => return Ok((values, token.span.end)),
+                ref kind => return Err(QueryError::new(format!("expected `,` or `)`, found {}", kind), token.span.clone())),
+            }
+        }
+    }
+
+    fn expect_word(&mut self, what: &str) -> Result<(String, Range<usize>), QueryError> {
+        let token = self.next()?;
+        match &token.kind {
+            TokenKind::Word(word) => Ok((word.to_string(), token.span.clone())),
+            kind => Err(QueryError::new(format!("expected {}, found {}", what, kind), token.span.clone())),
+        }
+    }
+}
+
+fn is_go_term(word: &str) -> bool {
+    word.starts_with("GO:")
+}
+
+/// Parses a segment such as `F:EXP`, pointing errors at the aspect or
+/// status at fault.
+fn parse_segment(word: &str, span: Range<usize>) -> Result<Segment, QueryError> {
+    let (aspect, status) = word.split_once(':').ok_or_else(|| {
+        QueryError::new(format!("expected a segment (e.g. F:EXP), GO term, or filter, found `{}`", word), span.clone())
+    })?;
+    let status_start = span.start + aspect.len() + 1;
+
+    let aspect = Aspect::try_from(&*aspect.to_uppercase()).map_err(|_| {
+        QueryError::new(format!("unknown aspect `{}`, expected one of F, P, or C", aspect), span.start..status_start - 1)
+    })?;
+    let status = AnnotationStatus::try_from(&*status.to_uppercase()).map_err(|_| {
+        QueryError::new(
+            format!("unknown annotation status `{}`, expected one of EXP, OTHER, UNKNOWN, UNANNOTATED, or NEGATED", status),
+            status_start..span.end)
+    })?;
+    Ok(Segment::new(aspect, status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Aspect::*, AnnotationStatus::*};
+
+    fn segment(aspect: Aspect, status: AnnotationStatus) -> Query {
+        Segment::new(aspect, status).into()
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = Query::parse("F:EXP and (P:OTHER or P:EXP) and not C:UNANNOTATED and taxon = 3702").unwrap();
+        let expected = Query::Filter(
+            Box::new(Query::And(vec![
+                segment(MolecularFunction, KnownExperimental),
+                Query::Or(vec![segment(BiologicalProcess, KnownOther), segment(BiologicalProcess, KnownExperimental)]),
+                Query::Not(Box::new(segment(CellularComponent, Unannotated))),
+            ])),
+            AnnotationFilter::Taxon("3702".to_string()),
+        );
+        assert_eq!(query, expected);
+
+        // `and` binds tighter than `or`, and symbols work like keywords
+        let query: Query = "f:exp | !exact GO:0005739 & GO:0009536".parse().unwrap();
+        let term = |term: &str, include_descendants| Query::Predicate(Predicate::Term { term: term.to_string(), include_descendants });
+        let expected = Query::Or(vec![
+            segment(MolecularFunction, KnownExperimental),
+            Query::And(vec![Query::Not(Box::new(term("GO:0005739", false))), term("GO:0009536", true)]),
+        ]);
+        assert_eq!(query, expected);
+
+        let query = Query::parse("C:OTHER and not type in (other_rna, miRNA)").unwrap();
+        let types = Predicate::GeneProductType(vec!["other_rna".to_string(), "miRNA".to_string()]);
+        let expected = Query::And(vec![segment(CellularComponent, KnownOther), Query::Not(Box::new(types.into()))]);
+        assert_eq!(query, expected);
+
+        let query = Query::parse("gene in (ENO1, AT2G36530)").unwrap();
+        let genes = Predicate::Genes(vec!["ENO1".to_string(), "AT2G36530".to_string()]);
+        assert_eq!(query, Query::Predicate(genes));
+
+        let query = Query::parse("taxon == taxon:3702").unwrap();
+        assert_eq!(query, Query::Filter(Box::new(Query::All), AnnotationFilter::Taxon("3702".to_string())));
+
+        let query = Query::parse("evidence in (IDA, IMP) and P:EXP and reference = PMID and assigned_by = TAIR and date < 20180101").unwrap();
+        let expected = [
+            AnnotationFilter::EvidenceCode(vec!["IDA".to_string(), "IMP".to_string()]),
+            AnnotationFilter::ReferencePrefix("PMID".to_string()),
+            AnnotationFilter::AssignedBy(vec!["TAIR".to_string()]),
+            AnnotationFilter::Date { from: None, to: Some(20180100) },
+        ].iter().fold(segment(BiologicalProcess, KnownExperimental), |query, filter| {
+            Query::Filter(Box::new(query), filter.clone())
+        });
+        assert_eq!(query, expected);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |text: &str| Query::parse(text).unwrap_err();
+
+        let query = "F:EXP and P:EXPERIMENTAL";
+        assert_eq!(error(query).span, 12..24);
+        assert_eq!(error(query).highlight(query), "F:EXP and P:EXPERIMENTAL\n            ^^^^^^^^^^^^ unknown annotation status `EXPERIMENTAL`, \
+            expected one of EXP, OTHER, UNKNOWN, UNANNOTATED, or NEGATED");
+
+        assert_eq!(error("X:EXP").span, 0..1);
+        assert_eq!(error("(F:EXP or P:EXP").message, "unclosed `(`");
+        assert_eq!(error("F:EXP and").span, 9..9);
+        assert_eq!(error("F:EXP P:EXP").span, 6..11);
+        assert_eq!(error("F:EXP # P:EXP").span, 6..7);
+        assert_eq!(error("taxon = human").message, "invalid taxon ID `human`");
+        assert_eq!(error("F:EXP or taxon = 3702").span, 9..21);
+        assert_eq!(error("not taxon = 3702").message, "filters can only be combined with `and`");
+        assert_eq!(error("date >= 2018").message, "invalid date `2018`, expected YYYYMMDD");
+        assert_eq!(error("evidence in (IDA IMP)").span, 17..20);
+        assert_eq!(error("reference in (PMID)").message, "expected `=`, found `in`");
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use crate::{Annotation, AnnotationStatus, Aspect, Index, Ontology, Term};
+use crate::index::{classify, GeneIndex};
+use crate::propagation::Hit;
+
+/// The statuses which genes may have for a slim term, in the order they are
+/// written in a table. Genes are never Unannotated for a term.
+const SLIM_STATUSES: &[AnnotationStatus] = &[
+    AnnotationStatus::KnownExperimental,
+    AnnotationStatus::KnownOther,
+    AnnotationStatus::Unknown,
+    AnnotationStatus::Negated,
+];
+
+/// The annotations of an Index mapped to the terms of a GO slim, as done by
+/// `map2slim`.
+///
+/// Each annotation is mapped to its nearest slim terms: the slim terms which
+/// its own term is (or is transitively `is_a` or `part_of`), leaving out
+/// any which are ancestors of another of those slim terms. Annotations may
+/// map to more than one slim term, or to none at all.
+#[derive(Debug)]
+pub struct SlimMapping<'a> {
+    index: &'a Index<'a, 'a>,
+    slim_terms: Vec<&'a Term>,
+    mapped: HashMap<String, Vec<Hit<'a>>>,
+    unmapped: Vec<&'a Annotation<'a>>,
+}
+
+/// The number of genes mapped to a slim term, by their status for it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SlimCount {
+    pub term: String,
+    pub name: String,
+    pub aspect: Option<Aspect>,
+    pub genes: HashMap<AnnotationStatus, usize>,
+    /// The number of distinct genes mapped to the term, whatever their status.
+    pub total: usize,
+}
+
+impl<'a> SlimMapping<'a> {
+    /// Maps the annotations of an Index to the terms of a subset of the
+    /// ontology, such as `goslim_plant`. Obsolete terms are never used.
+    pub fn new(index: &'a Index, ontology: &'a Ontology, subset: &str) -> SlimMapping<'a> {
+        let slim_terms: Vec<&Term> = ontology.terms()
+            .filter(|term| !term.obsolete && term.subsets.iter().any(|s| s == subset))
+            .collect();
+        let slim_ids: HashSet<&str> = slim_terms.iter().map(|term| &*term.id).collect();
+
+        let mut mapped: HashMap<String, Vec<Hit>> = HashMap::new();
+        let mut unmapped = Vec::new();
+        let mut nearest: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (gene, annotations) in index.anno_index.values() {
+            for &annotation in annotations {
+                let term = ontology.resolve(annotation.go_term)
+                    .map_or(annotation.go_term, |term| &term.id);
+                let slims = nearest.entry(term)
+                    .or_insert_with(|| nearest_slims(ontology, &slim_ids, term));
+                if slims.is_empty() {
+                    unmapped.push(annotation);
+                }
+                for slim in slims.iter() {
+                    mapped.entry(slim.to_string()).or_default().push((*gene, annotation));
+                }
+            }
+        }
+
+        SlimMapping { index, slim_terms, mapped, unmapped }
+    }
+
+    /// The terms of the slim, in the order they appear in the ontology.
+    pub fn slim_terms(&self) -> impl Iterator<Item=&'a Term> + '_ {
+        self.slim_terms.iter().copied()
+    }
+
+    /// Every annotation which was mapped to a slim term.
+    pub fn annotations(&self, slim_term: &str) -> impl Iterator<Item=&'a Annotation<'a>> + '_ {
+        self.mapped.get(slim_term).into_iter().flatten().map(|&(_, annotation)| annotation)
+    }
+
+    /// The annotations which could not be mapped to any slim term.
+    pub fn unmapped(&self) -> &[&'a Annotation<'a>] {
+        &self.unmapped
+    }
+
+    /// Classifies the genes mapped to a slim term by the Aspect and
+    /// AnnotationStatus of their mapped annotations, using the options of
+    /// the Index.
+    pub fn gene_index(&self, slim_term: &str) -> GeneIndex<'a> {
+        let hits = self.mapped.get(slim_term).into_iter().flatten().copied();
+        classify(hits, &self.index.options)
+    }
+
+    /// Counts the genes mapped to each slim term by their status for it.
+    pub fn counts(&self) -> Vec<SlimCount> {
+        self.slim_terms.iter()
+            .map(|term| {
+                let mut genes = HashMap::new();
+                let mut total = HashSet::new();
+                for by_status in self.gene_index(&term.id).into_values() {
+                    for (status, status_genes) in by_status {
+                        *genes.entry(status).or_insert(0) += status_genes.len();
+                        total.extend(status_genes);
+                    }
+                }
+                SlimCount {
+                    term: term.id.to_string(),
+                    name: term.name.to_string(),
+                    aspect: term.aspect(),
+                    genes,
+                    total: total.len(),
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the gene counts of each slim term as a tab-separated table.
+    pub fn write_tsv<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        write!(writer, "GO ID\tName\tAspect")?;
+        for status in SLIM_STATUSES {
+            write!(writer, "\t{}", status.as_str())?;
+        }
+        writeln!(writer, "\tTotal")?;
+
+        for count in self.counts() {
+            let aspect = count.aspect.map_or("", |aspect| aspect.as_str());
+            write!(writer, "{}\t{}\t{}", count.term, count.name, aspect)?;
+            for status in SLIM_STATUSES {
+                write!(writer, "\t{}", count.genes.get(status).unwrap_or(&0))?;
+            }
+            writeln!(writer, "\t{}", count.total)?;
+        }
+        Ok(())
+    }
+}
+
+/// The slim terms nearest to a term: those which it is or descends from,
+/// but which no other such slim term descends from.
+fn nearest_slims<'o>(ontology: &'o Ontology, slim_ids: &HashSet<&str>, term: &'o str) -> Vec<&'o str> {
+    let mut candidates: Vec<&str> = ontology.ancestors(term);
+    candidates.insert(0, term);
+    candidates.retain(|candidate| slim_ids.contains(candidate));
+
+    candidates.iter()
+        .filter(|&&candidate| {
+            !candidates.iter().any(|&other| other != candidate && ontology.is_a(other, candidate))
+        })
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::{AnnotationRecord, EvidencePolicy, Gene, GeneRecord};
+
+    const GO_OBO: &str = "[Term]
+id: GO:0005575
+name: cellular_component
+namespace: cellular_component
+subset: goslim_plant
+
+[Term]
+id: GO:0043226
+name: organelle
+namespace: cellular_component
+is_a: GO:0005575 ! cellular_component
+subset: goslim_plant
+
+[Term]
+id: GO:0005634
+name: nucleus
+namespace: cellular_component
+is_a: GO:0043226 ! organelle
+subset: goslim_plant
+
+[Term]
+id: GO:0005730
+name: nucleolus
+namespace: cellular_component
+is_a: GO:0043226 ! organelle
+relationship: part_of GO:0005634 ! nucleus
+
+[Term]
+id: GO:0005576
+name: extracellular region
+namespace: cellular_component
+is_a: GO:0005575 ! cellular_component
+";
+
+    fn record(gene: &str, go_term: &str, evidence_code: &str) -> AnnotationRecord {
+        AnnotationRecord {
+            db: "TAIR".to_string(),
+            database_id: "locus:2031476".to_string(),
+            db_object_symbol: "".to_string(),
+            invert: "".to_string(),
+            go_term: go_term.to_string(),
+            reference: "TAIR:Communication:1345790".to_string(),
+            evidence_code: evidence_code.to_string(),
+            additional_evidence: "".to_string(),
+            aspect: Aspect::CellularComponent,
+            unique_gene_name: gene.to_string(),
+            alternative_gene_name: "".to_string(),
+            gene_product_type: "protein".to_string(),
+            taxon: "taxon:3702".to_string(),
+            date: "20190907".to_string(),
+            assigned_by: "TAIR".to_string(),
+            annotation_extension: "".to_string(),
+            gene_product_form_id: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_map_to_slim() {
+        let ontology = Ontology::parse_from(Cursor::new(GO_OBO)).unwrap();
+        let gene_records: Vec<GeneRecord> = ["AT1G01010", "AT1G01020"].iter()
+            .map(|gene_id| GeneRecord {
+                gene_id: gene_id.to_string(),
+                gene_product_type: "protein".to_string(),
+            })
+            .collect();
+        let genes: Vec<Gene> = gene_records.iter().map(Gene::from_record).collect();
+
+        let anno_records = vec![
+            record("AT1G01010", "GO:0005730", "IDA"),
+            record("AT1G01020", "GO:0005634", "IEA"),
+            record("AT1G01020", "GO:0005576", "IEA"),
+            record("AT1G01020", "GO:0009999", "IEA"),
+        ];
+        let annotations: Vec<Annotation> = anno_records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .collect();
+        let index = Index::new(&genes, &annotations);
+        let slim = SlimMapping::new(&index, &ontology, "goslim_plant");
+
+        // The nucleolus maps only to the nucleus, not also to the organelle
+        assert_eq!(slim.annotations("GO:0005634").count(), 2);
+        assert_eq!(slim.annotations("GO:0043226").count(), 0);
+        assert_eq!(slim.annotations("GO:0005575").map(|anno| anno.go_term).collect::<Vec<_>>(), vec!["GO:0005576"]);
+        assert_eq!(slim.unmapped().iter().map(|anno| anno.go_term).collect::<Vec<_>>(), vec!["GO:0009999"]);
+
+        let counts = slim.counts();
+        let terms: Vec<&str> = counts.iter().map(|count| &*count.term).collect();
+        assert_eq!(terms, vec!["GO:0005575", "GO:0043226", "GO:0005634"]);
+        assert_eq!(counts[2].genes[&AnnotationStatus::KnownExperimental], 1);
+        assert_eq!(counts[2].genes[&AnnotationStatus::KnownOther], 1);
+        assert_eq!(counts[2].total, 2);
+        assert_eq!(counts[1].total, 0);
+
+        let mut tsv = Vec::new();
+        slim.write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert_eq!(tsv.lines().next(), Some("GO ID\tName\tAspect\tEXP\tOTHER\tUNKNOWN\tNEGATED\tTotal"));
+        assert_eq!(tsv.lines().nth(3), Some("GO:0005634\tnucleus\tC\t1\t1\t0\t0\t2"));
+    }
+}
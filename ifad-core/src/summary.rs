@@ -0,0 +1,179 @@
+use std::io::Write;
+use serde::Serialize;
+use crate::{AnnotationStatus, Aspect, Index, NegationPolicy, Segment};
+
+/// The number of Genes and annotations in each segment of an Index.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    /// The number of Genes in the Index.
+    pub genes: usize,
+    /// The number of annotations which were matched to a Gene.
+    pub annotations: usize,
+    pub segments: Vec<SegmentSummary>,
+}
+
+/// The number of Genes and annotations in one segment.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SegmentSummary {
+    pub aspect: Aspect,
+    pub status: AnnotationStatus,
+    pub genes: usize,
+    /// The percentage of all Genes which are in the segment.
+    pub gene_percentage: f64,
+    /// The number of annotations which place Genes in the segment.
+    pub annotations: usize,
+}
+
+impl Index<'_, '_> {
+    /// Counts the Genes and annotations in each segment.
+    ///
+    /// The Negated status is only included when `NOT` annotations are
+    /// classified separately.
+    pub fn summary(&self) -> Summary {
+        let mut statuses = vec![
+            AnnotationStatus::KnownExperimental,
+            AnnotationStatus::KnownOther,
+            AnnotationStatus::Unknown,
+            AnnotationStatus::Unannotated,
+        ];
+        if self.options.negation == NegationPolicy::Separate {
+            statuses.push(AnnotationStatus::Negated);
+        }
+
+        let total_genes = self.anno_index.len();
+        let segments = Aspect::ALL.iter()
+            .flat_map(|&aspect| statuses.iter().map(move |&status| Segment::new(aspect, status)))
+            .map(|segment| {
+                let result = segment.query(self);
+                let genes = result.genes_iter().count();
+                let gene_percentage = match total_genes {
+                    0 => 0.0,
+                    total => 100.0 * genes as f64 / total as f64,
+                };
+                SegmentSummary {
+                    aspect: segment.aspect(),
+                    status: segment.annotation_status(),
+                    genes,
+                    gene_percentage,
+                    annotations: result.annotations_iter().count(),
+                }
+            })
+            .collect();
+
+        Summary {
+            genes: total_genes,
+            annotations: self.anno_index.values().map(|(_, annotations)| annotations.len()).sum(),
+            segments,
+        }
+    }
+}
+
+impl Summary {
+    /// Writes the summary as a table aligned for reading in a terminal.
+    pub fn write_table<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, "{:<6}  {:<11}  {:>10}  {:>7}  {:>11}", "Aspect", "Status", "Genes", "%", "Annotations")?;
+        for segment in &self.segments {
+            writeln!(writer, "{:<6}  {:<11}  {:>10}  {:>6.1}%  {:>11}",
+                     segment.aspect.as_str(),
+                     segment.status.as_str(),
+                     segment.genes,
+                     segment.gene_percentage,
+                     segment.annotations)?;
+        }
+        writeln!(writer, "{} genes and {} annotations in total", self.genes, self.annotations)
+    }
+
+    /// Writes the summary as a tab-separated table.
+    pub fn write_tsv<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, "Aspect\tStatus\tGenes\tGene Percentage\tAnnotations")?;
+        for segment in &self.segments {
+            writeln!(writer, "{}\t{}\t{}\t{:.2}\t{}",
+                     segment.aspect.as_str(),
+                     segment.status.as_str(),
+                     segment.genes,
+                     segment.gene_percentage,
+                     segment.annotations)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the summary as a JSON object.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Annotation, AnnotationRecord, EvidencePolicy, Gene, GeneRecord};
+
+    fn record(gene: &str, aspect: Aspect, evidence_code: &str) -> AnnotationRecord {
+        AnnotationRecord {
+            db: "TAIR".to_string(),
+            database_id: "locus:2031476".to_string(),
+            db_object_symbol: "".to_string(),
+            invert: "".to_string(),
+            go_term: "GO:0005634".to_string(),
+            reference: "TAIR:Communication:1345790".to_string(),
+            evidence_code: evidence_code.to_string(),
+            additional_evidence: "".to_string(),
+            aspect,
+            unique_gene_name: gene.to_string(),
+            alternative_gene_name: "".to_string(),
+            gene_product_type: "protein".to_string(),
+            taxon: "taxon:3702".to_string(),
+            date: "20190907".to_string(),
+            assigned_by: "TAIR".to_string(),
+            annotation_extension: "".to_string(),
+            gene_product_form_id: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let gene_records: Vec<GeneRecord> = ["AT1G01010", "AT1G01020", "AT1G01030", "AT1G01040"].iter()
+            .map(|gene_id| GeneRecord {
+                gene_id: gene_id.to_string(),
+                gene_product_type: "protein".to_string(),
+            })
+            .collect();
+        let genes: Vec<Gene> = gene_records.iter().map(Gene::from_record).collect();
+        let anno_records = vec![
+            record("AT1G01010", Aspect::CellularComponent, "IDA"),
+            record("AT1G01010", Aspect::CellularComponent, "IMP"),
+            record("AT1G01020", Aspect::CellularComponent, "IEA"),
+            record("AT1G01030", Aspect::MolecularFunction, "ND"),
+        ];
+        let annotations: Vec<Annotation> = anno_records.iter()
+            .map(|record| Annotation::from_record(record, &EvidencePolicy::default()))
+            .collect();
+        let index = Index::new(&genes, &annotations);
+
+        let summary = index.summary();
+        assert_eq!((summary.genes, summary.annotations), (4, 4));
+        assert_eq!(summary.segments.len(), 12);
+        let segment = |aspect, status| summary.segments.iter()
+            .find(|segment| segment.aspect == aspect && segment.status == status)
+            .unwrap();
+        let cc_exp = segment(Aspect::CellularComponent, AnnotationStatus::KnownExperimental);
+        assert_eq!((cc_exp.genes, cc_exp.annotations), (1, 2));
+        assert!((cc_exp.gene_percentage - 25.0).abs() < 1e-12);
+        let cc_unannotated = segment(Aspect::CellularComponent, AnnotationStatus::Unannotated);
+        assert_eq!((cc_unannotated.genes, cc_unannotated.annotations), (2, 0));
+        assert_eq!(segment(Aspect::BiologicalProcess, AnnotationStatus::Unannotated).genes, 4);
+
+        let mut tsv = Vec::new();
+        summary.write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert_eq!(tsv.lines().nth(9), Some("C\tEXP\t1\t25.00\t2"));
+
+        let mut json = Vec::new();
+        summary.write_json(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["segments"][8]["aspect"], "C");
+        assert_eq!(json["segments"][8]["status"], "EXP");
+        assert_eq!(json["segments"][8]["genes"], 1);
+    }
+}
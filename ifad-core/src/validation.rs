@@ -0,0 +1,239 @@
+use std::convert::TryFrom;
+use crate::{Aspect, AnnotationRecord, Diagnostic, Diagnostics, Ontology, Qualifier, Relation};
+
+/// What to do with annotations which disagree with the ontology, either
+/// because their Aspect does not match the namespace of their GO term or
+/// because their GO term is obsolete.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum ValidationPolicy {
+    /// Keep the annotations as they are, only reporting them.
+    #[default]
+    Report,
+    /// Give annotations the Aspect of their GO term's namespace, along with
+    /// GO's default relation for it if their relation belongs to another
+    /// Aspect. Annotations to obsolete terms are kept, since correcting them
+    /// means choosing a new term.
+    Correct,
+    /// Leave out every annotation which disagrees with the ontology.
+    Drop,
+}
+
+impl ValidationPolicy {
+    /// Validates annotation records against an ontology, correcting or
+    /// dropping them according to this policy. Every problem found is
+    /// reported, whatever was done about it.
+    ///
+    /// This works on records rather than Annotations so that it can be done
+    /// before the Annotations are classified, and so that any corrections
+    /// are also written when the records are exported.
+    pub fn apply(&self, records: &mut Vec<AnnotationRecord>, ontology: &Ontology) -> Diagnostics {
+        let mut diagnostics = Diagnostics::new();
+        records.retain_mut(|record| {
+            let problems = validate(record, ontology);
+            let valid = problems.is_empty();
+            for problem in problems {
+                if let (ValidationPolicy::Correct, Diagnostic::AspectMismatch { expected, .. }) = (self, &problem) {
+                    correct_aspect(record, *expected);
+                }
+                diagnostics.push(problem);
+            }
+            valid || *self != ValidationPolicy::Drop
+        });
+        diagnostics
+    }
+}
+
+/// Gives an annotation record a new Aspect, replacing a relation which
+/// belongs to another Aspect (e.g. `enables` for a component) with the
+/// default relation for the new one.
+fn correct_aspect(record: &mut AnnotationRecord, aspect: Aspect) {
+    record.aspect = aspect;
+
+    // A qualifier which is not understood has no relation to replace
+    let qualifier = Qualifier::try_from(&*record.invert).unwrap_or_default();
+    if qualifier.relation.is_some_and(|relation| relation.aspect() != aspect) {
+        let relation = Relation::default_for(aspect);
+        record.invert = match qualifier.negated {
+            true => format!("NOT|{}", relation),
+            false => relation.to_string(),
+        };
+    }
+}
+
+/// Rewrites the GO term of each annotation record to its primary ID, so
+/// that annotations to alternative IDs (e.g. of merged terms) and to
+/// obsolete terms with a replacement use the current term. Every record
+/// which was changed is reported.
+///
+/// This should be done before validating records, since a replaced term is
+/// no longer obsolete.
+pub fn normalize_terms(records: &mut [AnnotationRecord], ontology: &Ontology) -> Diagnostics {
+    let mut diagnostics = Diagnostics::new();
+    for record in records {
+        let primary_id = match ontology.primary_id(&record.go_term) {
+            Some(primary_id) if primary_id != record.go_term => primary_id,
+            _ => continue,
+        };
+        let original_term = std::mem::replace(&mut record.go_term, primary_id.to_string());
+        diagnostics.push(Diagnostic::RemappedTerm {
+            gene_name: record.unique_gene_name.to_string(),
+            original_term,
+            go_term: record.go_term.to_string(),
+        });
+    }
+    diagnostics
+}
+
+/// Finds the ways an annotation record disagrees with an ontology.
+///
+/// Annotations to terms which are not in the ontology are not reported,
+/// since the ontology may only be a subset such as a GO slim.
+pub(crate) fn validate(record: &AnnotationRecord, ontology: &Ontology) -> Vec<Diagnostic> {
+    let mut problems = Vec::new();
+    let term = match ontology.resolve(&record.go_term) {
+        Some(term) => term,
+        None => return problems,
+    };
+
+    if term.obsolete {
+        problems.push(Diagnostic::ObsoleteTerm {
+            gene_name: record.unique_gene_name.to_string(),
+            go_term: record.go_term.to_string(),
+            replaced_by: term.replaced_by.clone(),
+        });
+    }
+    if let Some(expected) = term.aspect().filter(|&expected| expected != record.aspect) {
+        problems.push(Diagnostic::AspectMismatch {
+            gene_name: record.unique_gene_name.to_string(),
+            go_term: record.go_term.to_string(),
+            aspect: record.aspect,
+            expected,
+        });
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Aspect, Term};
+
+    fn record(go_term: &str, aspect: Aspect) -> AnnotationRecord {
+        AnnotationRecord {
+            db: "TAIR".to_string(),
+            database_id: "locus:2031476".to_string(),
+            db_object_symbol: "ENO1".to_string(),
+            invert: "".to_string(),
+            go_term: go_term.to_string(),
+            reference: "TAIR:Communication:1345790".to_string(),
+            evidence_code: "IDA".to_string(),
+            additional_evidence: "".to_string(),
+            aspect,
+            unique_gene_name: "AT1G74030".to_string(),
+            alternative_gene_name: "".to_string(),
+            gene_product_type: "protein".to_string(),
+            taxon: "taxon:3702".to_string(),
+            date: "20190907".to_string(),
+            assigned_by: "TAIR".to_string(),
+            annotation_extension: "".to_string(),
+            gene_product_form_id: "".to_string(),
+        }
+    }
+
+    fn ontology() -> Ontology {
+        Ontology::from_terms(vec![
+            Term {
+                id: "GO:0005634".to_string(),
+                name: "nucleus".to_string(),
+                namespace: "cellular_component".to_string(),
+                alt_ids: vec!["GO:0005633".to_string()],
+                ..Term::default()
+            },
+            Term {
+                id: "GO:0005635".to_string(),
+                name: "obsolete nuclear envelope".to_string(),
+                namespace: "cellular_component".to_string(),
+                obsolete: true,
+                replaced_by: Some("GO:0005634".to_string()),
+                ..Term::default()
+            },
+        ])
+    }
+
+    #[test]
+    fn test_validate_records() {
+        let ontology = ontology();
+        let records = vec![
+            record("GO:0005634", Aspect::CellularComponent),
+            record("GO:0005633", Aspect::MolecularFunction),
+            record("GO:0005635", Aspect::CellularComponent),
+            record("GO:0009999", Aspect::BiologicalProcess),
+        ];
+
+        let mut reported = records.clone();
+        let diagnostics = ValidationPolicy::Report.apply(&mut reported, &ontology);
+        assert_eq!(reported, records);
+        assert_eq!(diagnostics.iter().collect::<Vec<_>>(), vec![
+            &Diagnostic::AspectMismatch {
+                gene_name: "AT1G74030".to_string(),
+                go_term: "GO:0005633".to_string(),
+                aspect: Aspect::MolecularFunction,
+                expected: Aspect::CellularComponent,
+            },
+            &Diagnostic::ObsoleteTerm {
+                gene_name: "AT1G74030".to_string(),
+                go_term: "GO:0005635".to_string(),
+                replaced_by: Some("GO:0005634".to_string()),
+            },
+        ]);
+
+        let mut corrected = records.clone();
+        ValidationPolicy::Correct.apply(&mut corrected, &ontology);
+        assert_eq!(corrected.len(), 4);
+        assert_eq!(corrected[1].aspect, Aspect::CellularComponent);
+
+        // Relations are corrected along with the Aspect
+        let mut corrected = vec![
+            AnnotationRecord { invert: "NOT|enables".to_string(), ..record("GO:0005634", Aspect::MolecularFunction) },
+            AnnotationRecord { invert: "is_active_in".to_string(), ..record("GO:0005634", Aspect::MolecularFunction) },
+        ];
+        ValidationPolicy::Correct.apply(&mut corrected, &ontology);
+        assert_eq!(corrected[0].invert, "NOT|located_in");
+        assert_eq!(corrected[1].invert, "is_active_in");
+
+        let mut dropped = records.clone();
+        let diagnostics = ValidationPolicy::Drop.apply(&mut dropped, &ontology);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(dropped, vec![records[0].clone(), records[3].clone()]);
+    }
+
+    #[test]
+    fn test_normalize_terms() {
+        let ontology = ontology();
+        let mut records = vec![
+            record("GO:0005634", Aspect::CellularComponent),
+            record("GO:0005633", Aspect::CellularComponent),
+            record("GO:0005635", Aspect::CellularComponent),
+            record("GO:0009999", Aspect::BiologicalProcess),
+        ];
+
+        let diagnostics = normalize_terms(&mut records, &ontology);
+        let terms: Vec<&str> = records.iter().map(|record| &*record.go_term).collect();
+        assert_eq!(terms, vec!["GO:0005634", "GO:0005634", "GO:0005634", "GO:0009999"]);
+        assert_eq!(diagnostics.iter().collect::<Vec<_>>(), vec![
+            &Diagnostic::RemappedTerm {
+                gene_name: "AT1G74030".to_string(),
+                original_term: "GO:0005633".to_string(),
+                go_term: "GO:0005634".to_string(),
+            },
+            &Diagnostic::RemappedTerm {
+                gene_name: "AT1G74030".to_string(),
+                original_term: "GO:0005635".to_string(),
+                go_term: "GO:0005634".to_string(),
+            },
+        ]);
+
+        // Normalized records no longer refer to obsolete terms
+        assert!(ValidationPolicy::Report.apply(&mut records, &ontology).is_empty());
+    }
+}